@@ -1,9 +1,9 @@
 use crate::bootstrap::ICON_FONT;
-use crate::db::{Class, Lesson, LessonAbs};
-use crate::themes::{self, styled_menu_button, text_fg, text_fg_succes, ColorType};
+use crate::db::{AbsenceId, Change, ExcuseStatus, LessonAbs, StudentId, Subject};
+use crate::themes::{self, styled_button, styled_menu_button, text_fg, text_fg_succes, ColorType};
 use crate::time;
 use crate::toast::Toast;
-use crate::{absences, bootstrap::*};
+use crate::{absences, bootstrap::*, spans};
 use crate::{App, Message, ViewControl};
 use chrono::Datelike;
 use iced::widget::{
@@ -16,63 +16,294 @@ use iced::{Element, Function};
 
 async fn nothing() {}
 
+#[derive(Debug, Clone, Default)]
+pub struct AbsState {
+    /// The recorded absence loaded into the form
+    editing: Option<AbsenceId>,
+    /// The absence whose details are shown
+    detail: Option<AbsenceId>,
+    confirm_delete: Option<AbsenceId>,
+}
+
 #[derive(Debug, Clone)]
 pub enum AbsMsg {
-    SelectClass(Lesson),
+    SelectClass(Subject),
     NextTime,
     LastTime,
     NextDate,
     LastDate,
     Add,
-    TogglePerson(Class),
+    TogglePerson(StudentId),
     FirstLessonToggle,
     SmartNew,
+    Edit(AbsenceId),
+    CancelEdit,
+    Detail(AbsenceId),
+    Delete(AbsenceId),
+    ConfirmDelete,
+    CancelDelete,
+}
+
+/// The lesson picked in the form, on one held on its date
+fn form_slot(app: &App) -> time::LessonSlot {
+    time::snap(&app.settings.schedules, app.abs.date, app.abs.lesson_time)
+}
+
+fn set_form_slot(app: &mut App, slot: time::LessonSlot) {
+    app.abs.date = slot.date;
+    app.abs.lesson_time = slot.start(&app.settings.schedules);
 }
 
 pub fn handle_absences(msg: AbsMsg, app: &mut App) -> Task<Message> {
     match msg {
-        AbsMsg::SelectClass(lesson) => app.abs.lesson = lesson,
-        AbsMsg::NextTime => app.abs.lesson_time = time::get_next_lesson(app.abs.lesson_time),
-        AbsMsg::LastTime => app.abs.lesson_time = time::get_prev_lesson(app.abs.lesson_time),
+        AbsMsg::SelectClass(lesson) => app.abs.lesson = lesson.id,
+        AbsMsg::NextTime => {
+            let settings = &app.settings;
+            let slot = time::next_lesson(&settings.schedules, &settings.calendar, form_slot(app));
+            set_form_slot(app, slot);
+        }
+        AbsMsg::LastTime => {
+            let settings = &app.settings;
+            let slot = time::prev_lesson(&settings.schedules, &settings.calendar, form_slot(app));
+            set_form_slot(app, slot);
+        }
         AbsMsg::NextDate => {
-            app.abs.date = app.abs.date.succ_opt().expect("Theres no tommorow?");
+            app.abs.date = app.settings.calendar.next_school_day(app.abs.date);
+            set_form_slot(app, form_slot(app));
         }
         AbsMsg::LastDate => {
-            app.abs.date = app.abs.date.pred_opt().expect("There was no yesterday?")
+            app.abs.date = app.settings.calendar.prev_school_day(app.abs.date);
+            set_form_slot(app, form_slot(app));
         }
         AbsMsg::Add => {
-            app.db.absences.push(app.abs.clone());
-            app.notify(Toast::new("Absenz erfasst", "idk was da anemuen? Treffen sich zwei jäger", crate::toast::Status::Success));
+            let mut abs = app.abs.clone();
+            let settings = &app.settings;
+            app.db.sync_records(&mut abs, settings.excuse_days, &settings.calendar);
+            match app.absences.editing.take() {
+                Some(_) => {
+                    app.commit(Change::UpdateAbsence(abs));
+                    app.notify(Toast::new(
+                        "Absenz geändert",
+                        &app.db.subject_name(app.abs.lesson),
+                        crate::toast::Status::Success,
+                    ));
+                    app.abs = app.fresh_absence();
+                }
+                None => {
+                    abs.id = app.db.next_absence_id();
+                    app.commit(Change::AddAbsence(abs));
+                    app.notify(Toast::new("Absenz erfasst", "idk was da anemuen? Treffen sich zwei jäger", crate::toast::Status::Success));
+                }
+            }
             return Task::perform(nothing(), |_| Message::BackView);
         },
         AbsMsg::TogglePerson(p) => app.abs.toggle_person(p),
         AbsMsg::FirstLessonToggle => app.abs.first_lesson = !app.abs.first_lesson,
-        AbsMsg::SmartNew => app.abs = LessonAbs::new_smart(&app.db.absences),
+        AbsMsg::SmartNew => {
+            app.absences.editing = None;
+            app.abs = app.fresh_absence();
+        }
+        AbsMsg::Edit(id) => {
+            let Some(a) = app.db.absence(id).cloned() else {
+                return Task::none();
+            };
+            if a.archived_absent.is_some() {
+                app.notify(Toast::new(
+                    "Nicht bearbeitbar",
+                    "Die Namen dieser Absenz wurden entfernt",
+                    crate::toast::Status::Danger,
+                ));
+                return Task::none();
+            }
+            app.abs = a;
+            app.absences.editing = Some(id);
+            if !matches!(app.view, ViewControl::ABSENCES) {
+                app.view_origin = app.view.clone();
+                app.view = ViewControl::ABSENCES;
+            }
+        }
+        AbsMsg::CancelEdit => {
+            app.absences.editing = None;
+            app.abs = app.fresh_absence();
+        }
+        AbsMsg::Detail(id) => {
+            app.absences.detail = match app.absences.detail {
+                Some(d) if d == id => None,
+                _ => Some(id),
+            };
+        }
+        AbsMsg::Delete(id) => app.absences.confirm_delete = Some(id),
+        AbsMsg::CancelDelete => app.absences.confirm_delete = None,
+        AbsMsg::ConfirmDelete => {
+            let Some(id) = app.absences.confirm_delete.take() else {
+                return Task::none();
+            };
+            let what = app
+                .db
+                .absence(id)
+                .map(|a| {
+                    format!(
+                        "{} am {}",
+                        app.db.subject_name(a.lesson),
+                        a.date.format("%d.%m.%Y")
+                    )
+                })
+                .unwrap_or_default();
+            app.commit_undoable(Change::DeleteAbsence(id), "Absenz gelöscht", &what);
+            if app.absences.editing == Some(id) {
+                app.absences.editing = None;
+                app.abs = app.fresh_absence();
+            }
+            if app.absences.detail == Some(id) {
+                app.absences.detail = None;
+            }
+        }
     }
     Task::none()
 }
 
+/// Edit and delete buttons of a recorded absence, asking before it is deleted
+pub fn absence_actions(app: &App, id: AbsenceId) -> Element<Message> {
+    if app.absences.confirm_delete == Some(id) {
+        return row![
+            text("Löschen?").style(themes::text_fg_danger),
+            styled_button(
+                Bootstrap::TrashthreeFill,
+                "Ja",
+                Message::Abs(AbsMsg::ConfirmDelete),
+                ColorType::Danger,
+            ),
+            styled_button(
+                Bootstrap::XSquareFill,
+                "Nein",
+                Message::Abs(AbsMsg::CancelDelete),
+                ColorType::Secondary,
+            ),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center)
+        .into();
+    }
+    row![
+        button(
+            text(icon_to_string(Bootstrap::InfoCircleFill))
+                .font(ICON_FONT)
+                .size(22)
+                .style(themes::text_fg)
+        )
+        .on_press(Message::Abs(AbsMsg::Detail(id)))
+        .style(button::text),
+        button(
+            text(icon_to_string(Bootstrap::PencilFill))
+                .font(ICON_FONT)
+                .size(22)
+                .style(themes::text_fg)
+        )
+        .on_press(Message::Abs(AbsMsg::Edit(id)))
+        .style(button::text),
+        button(
+            text(icon_to_string(Bootstrap::TrashthreeFill))
+                .font(ICON_FONT)
+                .size(22)
+                .style(themes::text_fg_danger)
+        )
+        .on_press(Message::Abs(AbsMsg::Delete(id)))
+        .style(button::text),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center)
+    .into()
+}
+
+/// Everyone missing in `a` with the state of their excuse
+pub fn absence_detail<'a>(app: &'a App, a: &'a LessonAbs) -> Element<'a, Message> {
+    let mut col = column![text(format!(
+        "{}, {} um {}{}",
+        app.db.subject_name(a.lesson),
+        a.date.format("%d.%m.%Y"),
+        a.lesson_time.format("%H:%M"),
+        if a.first_lesson { ", erste Lektion" } else { "" }
+    ))
+    .style(themes::text_fg)]
+    .spacing(2)
+    .padding(10);
+    if let Some(n) = a.archived_absent {
+        col = col.push(
+            text(format!("{} abwesend, die Namen wurden entfernt", n)).style(themes::text_fg_sec),
+        );
+        return col.into();
+    }
+    if a.records.is_empty() {
+        col = col.push(text("Alle anwesend").style(text_fg_succes));
+    }
+    for r in &a.records {
+        let mut line = row![
+            text(app.db.student_name(r.student)).width(200),
+            text(r.status.to_string()).style(match r.status {
+                ExcuseStatus::Open => themes::text_fg,
+                ExcuseStatus::Excused => themes::text_fg_succes,
+                ExcuseStatus::Unexcused => themes::text_fg_danger,
+            }),
+        ]
+        .spacing(10);
+        if r.status == ExcuseStatus::Open {
+            line = line.push(
+                text(format!("Frist {}", r.deadline.format("%d.%m.%Y")))
+                    .style(themes::text_fg_sec),
+            );
+        }
+        if !r.note.is_empty() {
+            line = line.push(text(r.note.clone()).style(themes::text_fg_sec));
+        }
+        col = col.push(line);
+    }
+    container(col).style(container::bordered_box).into()
+}
+
 pub fn absences_view(app: &App) -> Element<Message> {
     let mut grid = column![].padding(10).spacing(5);
 
-    for (i, absence) in app.db.absences.iter().enumerate() {
+    for (i, absence) in app
+        .db
+        .absences
+        .iter()
+        .filter(|a| Some(a.cohort) == app.cohort)
+        .enumerate()
+    {
         grid = grid.push(
-            container(row![text(absence.lesson.to_string())].padding(10)).style(
-                move |a: &Theme| {
-                    if i % 2 == 0 {
-                        container::primary(a)
-                    } else {
-                        container::dark(a)
-                    }
-                },
-            ),
+            container(
+                row![
+                    text(app.db.subject_name(absence.lesson)),
+                    text(format!(
+                        "{} {}",
+                        absence.date.format("%d.%m.%Y"),
+                        absence.lesson_time.format("%H:%M")
+                    )),
+                    text(format!("{} abwesend", absence.absent_count())),
+                    horizontal_space(),
+                    absence_actions(app, absence.id),
+                ]
+                .spacing(10)
+                .align_y(Alignment::Center)
+                .padding(10),
+            )
+            .style(move |a: &Theme| {
+                if i % 2 == 0 {
+                    container::primary(a)
+                } else {
+                    container::dark(a)
+                }
+            }),
         );
+        if app.absences.detail == Some(absence.id) {
+            grid = grid.push(absence_detail(app, absence));
+        }
     }
 
     let mut person_grid = column![];
-    for (i, p) in Class::all().into_iter().enumerate() {
-        let p_c = p.clone();
-        let is_checked = app.abs.present.contains(&p);
+    for (i, p) in app.db.roster(app.cohort).into_iter().enumerate() {
+        let id = p.id;
+        let is_checked = app.abs.present.contains(&id);
         person_grid = person_grid.push(
             mouse_area(
                 container(
@@ -85,7 +316,7 @@ pub fn absences_view(app: &App) -> Element<Message> {
                             .size(18),
                         horizontal_space(),
                         toggler(is_checked)
-                            .on_toggle(move |_| Message::Abs(AbsMsg::TogglePerson(p.clone())))
+                            .on_toggle(move |_| Message::Abs(AbsMsg::TogglePerson(id)))
                     ]
                     .align_y(Alignment::Center)
                     .padding(10),
@@ -98,7 +329,7 @@ pub fn absences_view(app: &App) -> Element<Message> {
                     }
                 }),
             )
-            .on_press(Message::Abs(AbsMsg::TogglePerson(p_c))),
+            .on_press(Message::Abs(AbsMsg::TogglePerson(id))),
         )
     }
 
@@ -157,19 +388,37 @@ pub fn absences_view(app: &App) -> Element<Message> {
         .on_press(Message::Abs(AbsMsg::SmartNew))
         .style(button::secondary);
 
-    let add = styled_menu_button(
-        Bootstrap::CheckSquareFill,
-        "Hinzufügen",
-        Message::Abs(AbsMsg::Add),
-        ColorType::Succes,
-    );
+    let add = match app.absences.editing {
+        Some(_) => row![
+            styled_menu_button(
+                Bootstrap::CheckSquareFill,
+                "Speichern",
+                Message::Abs(AbsMsg::Add),
+                ColorType::Succes,
+            ),
+            styled_menu_button(
+                Bootstrap::XSquareFill,
+                "Abbrechen",
+                Message::Abs(AbsMsg::CancelEdit),
+                ColorType::Secondary,
+            ),
+        ],
+        None => row![styled_menu_button(
+            Bootstrap::CheckSquareFill,
+            "Hinzufügen",
+            Message::Abs(AbsMsg::Add),
+            ColorType::Succes,
+        )],
+    };
 
     let add_abs = container(column![
         row![
             reload_b,
-            pick_list(Lesson::all(), Some(app.abs.lesson.clone()), |l| {
-                Message::Abs(AbsMsg::SelectClass(l))
-            }),
+            pick_list(
+                app.db.active_subjects(app.cohort),
+                app.db.subject(app.abs.lesson).cloned(),
+                |l| Message::Abs(AbsMsg::SelectClass(l))
+            ),
             date_picker,
             time_picker,
             fl,
@@ -180,6 +429,8 @@ pub fn absences_view(app: &App) -> Element<Message> {
         .spacing(10)
         .align_y(Alignment::Center),
         person_grid,
+        horizontal_rule(1),
+        spans::span_view(app),
     ]);
 
     container(
@@ -0,0 +1,383 @@
+use crate::bootstrap::*;
+use crate::crypt;
+use crate::db::{Change, DataBase, DataBaseError};
+use crate::themes::{self, styled_button, ColorType};
+use crate::toast::{Status, Toast};
+use crate::{paths, App, Message};
+use chrono::{Datelike, Local, NaiveDateTime};
+use iced::widget::{button, column, horizontal_space, row, text};
+use iced::{Alignment, Element, Task};
+use std::path::Path;
+
+pub const BACKUP_DIR: &str = "backups";
+/// Automatic backups kept per day and per month, manual ones are never removed
+pub const KEEP_DAILY: usize = 7;
+pub const KEEP_MONTHLY: usize = 12;
+
+const NAME_FORMAT: &str = "%Y-%m-%d_%H-%M-%S";
+
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub path: String,
+    pub created: NaiveDateTime,
+    pub auto: bool,
+    pub contents: Contents,
+}
+
+/// What could be read of a backup when the folder was listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contents {
+    Counts {
+        entries: usize,
+        absences: usize,
+    },
+    /// Encrypted with another passphrase, or none is entered
+    Locked,
+    Unreadable,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BackupState {
+    list: Vec<BackupInfo>,
+    selected: Option<String>,
+    confirm: bool,
+}
+
+impl BackupState {
+    /// The backups found the last time the folder was read, newest first
+    pub fn list(&self) -> &[BackupInfo] {
+        &self.list
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BackupMsg {
+    Refresh,
+    Listed(Vec<BackupInfo>),
+    Create,
+    Created(Result<String, DataBaseError>),
+    AutoCreated(Result<Option<String>, DataBaseError>),
+    Select(String),
+    Restore,
+    Confirm,
+    Cancel,
+    Restored(Result<DataBase, DataBaseError>),
+}
+
+pub fn backup_dir() -> String {
+    paths::data_file(BACKUP_DIR)
+}
+
+/// `db-2025-03-14_07-45-00.json`, automatic ones end in `-auto`
+fn file_name(created: NaiveDateTime, auto: bool) -> String {
+    format!(
+        "db-{}{}.json",
+        created.format(NAME_FORMAT),
+        if auto { "-auto" } else { "" }
+    )
+}
+
+fn parse_name(name: &str) -> Option<(NaiveDateTime, bool)> {
+    let stem = name.strip_prefix("db-")?.strip_suffix(".json")?;
+    let (stamp, auto) = match stem.strip_suffix("-auto") {
+        Some(s) => (s, true),
+        None => (stem, false),
+    };
+    let created = NaiveDateTime::parse_from_str(stamp, NAME_FORMAT).ok()?;
+    Some((created, auto))
+}
+
+/// Counts the records without going through the migrations, so listing
+/// never writes anything next to the backups. Backups that don't open are
+/// listed as locked or unreadable instead of as empty.
+fn read_info(path: &Path) -> Option<BackupInfo> {
+    let (created, auto) = parse_name(path.file_name()?.to_str()?)?;
+    let content = std::fs::read_to_string(path).ok()?;
+    let contents = match crypt::open(&content) {
+        Err(DataBaseError::Locked | DataBaseError::WrongPassphrase) => Contents::Locked,
+        Err(_) => Contents::Unreadable,
+        Ok(plain) => match serde_json::from_str::<serde_json::Value>(&plain) {
+            Ok(doc) => {
+                let count = |key: &str| {
+                    doc.get(key)
+                        .and_then(|v| v.as_array())
+                        .map_or(0, |a| a.len())
+                };
+                Contents::Counts {
+                    entries: count("data"),
+                    absences: count("absences"),
+                }
+            }
+            Err(_) => Contents::Unreadable,
+        },
+    };
+    Some(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        created,
+        auto,
+        contents,
+    })
+}
+
+/// All readable backups, newest first
+pub fn list_backups() -> Vec<BackupInfo> {
+    let Ok(dir) = std::fs::read_dir(backup_dir()) else {
+        return vec![];
+    };
+    let mut list: Vec<BackupInfo> = dir
+        .filter_map(|e| e.ok())
+        .filter_map(|e| read_info(&e.path()))
+        .collect();
+    list.sort_by(|a, b| b.created.cmp(&a.created));
+    list
+}
+
+pub fn write_backup(db: &DataBase, auto: bool) -> Result<String, DataBaseError> {
+    let dir = backup_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| DataBaseError::from(e).at(&dir))?;
+    let name = file_name(Local::now().naive_local(), auto);
+    let path = Path::new(&dir).join(name).to_string_lossy().to_string();
+    db.write_file(&path)?;
+    Ok(path)
+}
+
+/// The automatic backups that fall outside the retention policy. The newest
+/// backup of each of the last `daily` days and of the last `monthly` months
+/// is kept.
+pub fn to_prune(backups: &[BackupInfo], daily: usize, monthly: usize) -> Vec<String> {
+    let mut auto: Vec<&BackupInfo> = backups.iter().filter(|b| b.auto).collect();
+    auto.sort_by(|a, b| b.created.cmp(&a.created));
+    let mut days = vec![];
+    let mut months = vec![];
+    let mut prune = vec![];
+    for b in auto {
+        let day = b.created.date();
+        let month = (day.year(), day.month());
+        let mut keep = false;
+        if !days.contains(&day) && days.len() < daily {
+            days.push(day);
+            keep = true;
+        }
+        if !months.contains(&month) && months.len() < monthly {
+            months.push(month);
+            keep = true;
+        }
+        if !keep {
+            prune.push(b.path.clone());
+        }
+    }
+    prune
+}
+
+/// Writes the automatic backup of the day if there is none yet and applies
+/// the retention policy. Returns the path of the new backup.
+pub async fn auto_backup(db: DataBase) -> Result<Option<String>, DataBaseError> {
+    let today = Local::now().date_naive();
+    let backups = list_backups();
+    let created = match backups.iter().any(|b| b.auto && b.created.date() == today) {
+        true => None,
+        false => Some(write_backup(&db, true)?),
+    };
+    for path in to_prune(&list_backups(), KEEP_DAILY, KEEP_MONTHLY) {
+        std::fs::remove_file(&path).map_err(|e| DataBaseError::from(e).at(&path))?;
+    }
+    Ok(created)
+}
+
+/// Contents of every backup that opens with the current passphrase, used to
+/// write them again after the passphrase changed
+pub fn read_all_plain() -> Vec<(String, String)> {
+    list_backups()
+        .into_iter()
+        .filter_map(|b| {
+            let content = std::fs::read_to_string(&b.path).ok()?;
+            Some((b.path, crypt::open(&content).ok()?))
+        })
+        .collect()
+}
+
+/// Writes backups read by `read_all_plain` with the current passphrase
+pub fn rewrite_all(backups: Vec<(String, String)>) -> Result<(), DataBaseError> {
+    for (path, content) in backups {
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, crypt::seal(&content)?)
+            .and_then(|_| std::fs::rename(&tmp, &path))
+            .map_err(|e| DataBaseError::from(e).at(&path))?;
+    }
+    Ok(())
+}
+
+async fn create_backup(db: DataBase) -> Result<String, DataBaseError> {
+    write_backup(&db, false)
+}
+
+async fn load_backups() -> Vec<BackupInfo> {
+    list_backups()
+}
+
+pub fn update_backup(app: &mut App, msg: BackupMsg) -> Task<Message> {
+    match msg {
+        BackupMsg::Refresh => {
+            return Task::perform(load_backups(), |l| Message::Backup(BackupMsg::Listed(l)))
+        }
+        BackupMsg::Listed(list) => {
+            if app
+                .backup
+                .selected
+                .as_ref()
+                .is_some_and(|s| !list.iter().any(|b| &b.path == s))
+            {
+                app.backup.selected = None;
+                app.backup.confirm = false;
+            }
+            app.backup.list = list;
+        }
+        BackupMsg::Create => {
+            return Task::perform(create_backup(app.db.clone()), |r| {
+                Message::Backup(BackupMsg::Created(r))
+            })
+        }
+        BackupMsg::Created(Ok(path)) => {
+            app.notify(Toast::new("Backup erstellt", &path, Status::Success));
+            return Task::done(Message::Backup(BackupMsg::Refresh));
+        }
+        BackupMsg::Created(Err(e)) => {
+            return Task::done(Message::DBError("Backup fehlgeschlagen".to_string(), e))
+        }
+        BackupMsg::AutoCreated(Ok(_)) => return Task::done(Message::Backup(BackupMsg::Refresh)),
+        BackupMsg::AutoCreated(Err(e)) => {
+            return Task::done(Message::DBError(
+                "Automatisches Backup fehlgeschlagen".to_string(),
+                e,
+            ))
+        }
+        BackupMsg::Select(path) => {
+            app.backup.selected = Some(path);
+            app.backup.confirm = false;
+        }
+        BackupMsg::Restore => app.backup.confirm = app.backup.selected.is_some(),
+        BackupMsg::Cancel => app.backup.confirm = false,
+        BackupMsg::Confirm => {
+            app.backup.confirm = false;
+            if app.db_locked {
+                app.notify(Toast::new(
+                    "Nicht wiederhergestellt",
+                    "Die Datenbank ist gesperrt, siehe Fehlermeldung",
+                    Status::Danger,
+                ));
+                return Task::none();
+            }
+            if let Some(path) = app.backup.selected.clone() {
+                return Task::perform(async move { DataBase::read_only(&path) }, |r| {
+                    Message::Backup(BackupMsg::Restored(r))
+                });
+            }
+        }
+        BackupMsg::Restored(Ok(db)) => {
+            app.commit_undoable(
+                Change::Replace(Box::new(db)),
+                "Backup wiederhergestellt",
+                "Die bisherigen Daten wurden ersetzt",
+            );
+            app.fix_selection();
+        }
+        BackupMsg::Restored(Err(e)) => {
+            return Task::done(Message::DBError(
+                "Backup nicht wiederhergestellt".to_string(),
+                e,
+            ))
+        }
+    }
+    Task::none()
+}
+
+pub fn backup_view(app: &App) -> Element<Message> {
+    let mut list = column![].spacing(2);
+    for b in &app.backup.list {
+        let selected = app.backup.selected.as_ref() == Some(&b.path);
+        list = list.push(
+            button(
+                row![
+                    text(b.created.format("%d.%m.%Y %H:%M").to_string()).style(if selected {
+                        themes::text_fg_succes
+                    } else {
+                        themes::text_fg
+                    }),
+                    text(if b.auto { "automatisch" } else { "manuell" })
+                        .style(themes::text_fg_sec),
+                    horizontal_space(),
+                    match b.contents {
+                        Contents::Counts { entries, absences } => {
+                            text(format!("{} Verspätungen, {} Absenzen", entries, absences))
+                                .style(themes::text_fg_sec)
+                        }
+                        Contents::Locked => text("gesperrt").style(themes::text_fg_danger),
+                        Contents::Unreadable => text("nicht lesbar").style(themes::text_fg_danger),
+                    },
+                ]
+                .spacing(10)
+                .align_y(Alignment::Center),
+            )
+            .on_press(Message::Backup(BackupMsg::Select(b.path.clone())))
+            .style(button::text),
+        );
+    }
+    if app.backup.list.is_empty() {
+        list = list.push(text("Noch keine Backups").style(themes::text_fg_sec));
+    }
+
+    let actions = match (app.backup.selected.is_some(), app.backup.confirm) {
+        (true, true) => row![
+            text("Aktuelle Daten durch dieses Backup ersetzen?").style(themes::text_fg_danger),
+            styled_button(
+                Bootstrap::ArrowCounterclockwise,
+                "Ja, wiederherstellen",
+                Message::Backup(BackupMsg::Confirm),
+                ColorType::Danger,
+            ),
+            styled_button(
+                Bootstrap::XSquareFill,
+                "Abbrechen",
+                Message::Backup(BackupMsg::Cancel),
+                ColorType::Secondary,
+            ),
+        ],
+        (true, false) => row![styled_button(
+            Bootstrap::ArrowCounterclockwise,
+            "Wiederherstellen",
+            Message::Backup(BackupMsg::Restore),
+            ColorType::Primary,
+        )],
+        (false, _) => row![],
+    };
+
+    column![
+        text("Backups").size(22).style(themes::text_fg),
+        text(format!(
+            "Im Ordner {}, automatisch einmal täglich. Behalten werden {} tägliche und {} monatliche.",
+            backup_dir(),
+            KEEP_DAILY,
+            KEEP_MONTHLY
+        ))
+        .style(themes::text_fg_sec),
+        row![
+            styled_button(
+                Bootstrap::FileEarmarkArrowDownFill,
+                "Neues Backup",
+                Message::Backup(BackupMsg::Create),
+                ColorType::Succes,
+            ),
+            styled_button(
+                Bootstrap::ArrowClockwise,
+                "Aktualisieren",
+                Message::Backup(BackupMsg::Refresh),
+                ColorType::Secondary,
+            ),
+        ]
+        .spacing(5),
+        list,
+        actions.spacing(10).align_y(Alignment::Center),
+    ]
+    .spacing(5)
+    .into()
+}
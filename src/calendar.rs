@@ -0,0 +1,686 @@
+use crate::bootstrap::*;
+use crate::themes::{self, styled_button, ColorType};
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone};
+use iced::widget::{button, column, pick_list, row, text, text_input};
+use iced::{Alignment, Element, Task};
+use serde_derive::{Deserialize, Serialize};
+
+/// A semester or quarter, statistics can be limited to one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Term {
+    pub name: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+impl Term {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from <= date && date <= self.to
+    }
+}
+
+impl std::fmt::Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} bis {})",
+            self.name,
+            self.from.format("%d.%m.%Y"),
+            self.to.format("%d.%m.%Y")
+        )
+    }
+}
+
+/// Holidays or a single day without school
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DayOff {
+    pub name: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Calendar {
+    pub terms: Vec<Term>,
+    pub days_off: Vec<DayOff>,
+}
+
+impl Calendar {
+    /// The holidays or day off `date` falls on
+    pub fn day_off(&self, date: NaiveDate) -> Option<&DayOff> {
+        self.days_off
+            .iter()
+            .find(|d| d.from <= date && date <= d.to)
+    }
+
+    /// Weekdays that are not a day off
+    pub fn is_school_day(&self, date: NaiveDate) -> bool {
+        date.weekday().num_days_from_monday() < 5 && self.day_off(date).is_none()
+    }
+
+    /// The first school day after `date`
+    pub fn next_school_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut day = date.succ_opt().expect("Theres no tommorow?");
+        // A calendar that is all holidays still has to end somewhere
+        for _ in 0..366 {
+            if self.is_school_day(day) {
+                return day;
+            }
+            day = day.succ_opt().expect("Theres no tommorow?");
+        }
+        date.succ_opt().expect("Theres no tommorow?")
+    }
+
+    /// The last school day before `date`
+    pub fn prev_school_day(&self, date: NaiveDate) -> NaiveDate {
+        let mut day = date.pred_opt().expect("There was no yesterday?");
+        for _ in 0..366 {
+            if self.is_school_day(day) {
+                return day;
+            }
+            day = day.pred_opt().expect("There was no yesterday?");
+        }
+        date.pred_opt().expect("There was no yesterday?")
+    }
+
+    fn sort(&mut self) {
+        self.terms.sort_by_key(|t| t.from);
+        self.days_off.sort_by_key(|d| d.from);
+    }
+}
+
+/// An event of an iCalendar file, `to` is its last day
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IcsEvent {
+    name: String,
+    from: NaiveDate,
+    to: NaiveDate,
+    /// Whole days (`VALUE=DATE`) rather than a time span
+    all_day: bool,
+}
+
+/// A point in time of an event and whether it stands for a whole day
+type IcsTime = (NaiveDateTime, bool);
+
+/// A DTSTART or DTEND value as local time and whether it is a whole day.
+/// Times in UTC are converted to `tz`, times with a TZID are taken as they
+/// are.
+fn ics_time<Tz: TimeZone>(params: &str, value: &str, tz: &Tz) -> Option<IcsTime> {
+    let all_day = params
+        .split(';')
+        .any(|p| p.eq_ignore_ascii_case("VALUE=DATE"))
+        || !value.contains('T');
+    if all_day {
+        let day = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some((day.and_time(NaiveTime::MIN), true));
+    }
+    let (value, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    match utc {
+        true => Some((time.and_utc().with_timezone(tz).naive_local(), false)),
+        false => Some((time, false)),
+    }
+}
+
+/// Events of an iCalendar file, UTC times in the time zone `tz`
+fn parse_ics<Tz: TimeZone>(ics: &str, tz: &Tz) -> Result<Vec<IcsEvent>, String> {
+    // Long lines continue on the next one after a space or tab
+    let mut lines: Vec<String> = vec![];
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+    if !lines.iter().any(|l| l == "BEGIN:VCALENDAR") {
+        return Err("Keine iCalendar-Datei".to_string());
+    }
+
+    let mut events = vec![];
+    let mut event: Option<(String, Option<IcsTime>, Option<NaiveDateTime>)> = None;
+    for line in &lines {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, params) = key.split_once(';').unwrap_or((key, ""));
+        match name {
+            "BEGIN" if value == "VEVENT" => event = Some((String::new(), None, None)),
+            "END" if value == "VEVENT" => {
+                let Some((name, Some((start, all_day)), end)) = event.take() else {
+                    continue;
+                };
+                // DTEND is the first moment after the event, so neither the
+                // day after a whole day event nor a midnight end belong to it
+                let to = match end {
+                    Some(end) if end > start => (end - TimeDelta::seconds(1)).date(),
+                    _ => start.date(),
+                };
+                events.push(IcsEvent {
+                    name,
+                    from: start.date(),
+                    to,
+                    all_day,
+                });
+            }
+            _ => {
+                let Some(e) = event.as_mut() else {
+                    continue;
+                };
+                match name {
+                    "SUMMARY" => {
+                        e.0 = value
+                            .replace("\\n", " ")
+                            .replace("\\,", ",")
+                            .replace("\\;", ";")
+                            .replace("\\\\", "\\")
+                    }
+                    "DTSTART" => e.1 = ics_time(params, value, tz),
+                    "DTEND" => e.2 = ics_time(params, value, tz).map(|(t, _)| t),
+                    _ => (),
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// What an iCalendar import added, and how many events it left out
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Imported {
+    terms: usize,
+    days_off: usize,
+    /// Events with a time of day, a parents' evening is no day off
+    timed: usize,
+}
+
+/// Adds the whole day events of an iCalendar file. Events named like a
+/// semester or quarter become terms, everything else a day off. Events with
+/// a time of day are left out.
+fn import_ics(calendar: &mut Calendar, ics: &str) -> Result<Imported, String> {
+    let mut added = Imported::default();
+    for IcsEvent {
+        name,
+        from,
+        to,
+        all_day,
+    } in parse_ics(ics, &Local)?
+    {
+        if !all_day {
+            added.timed += 1;
+            continue;
+        }
+        let lower = name.to_lowercase();
+        if ["semester", "quartal", "trimester"]
+            .iter()
+            .any(|k| lower.contains(k))
+        {
+            let term = Term { name, from, to };
+            if !calendar.terms.contains(&term) {
+                calendar.terms.push(term);
+                added.terms += 1;
+            }
+        } else {
+            let day = DayOff { name, from, to };
+            if !calendar.days_off.contains(&day) {
+                calendar.days_off.push(day);
+                added.days_off += 1;
+            }
+        }
+    }
+    calendar.sort();
+    Ok(added)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Kind {
+    #[default]
+    DayOff,
+    Term,
+}
+
+impl Kind {
+    fn all() -> Vec<Self> {
+        vec![Kind::DayOff, Kind::Term]
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Kind::DayOff => write!(f, "Ferien / freier Tag"),
+            Kind::Term => write!(f, "Semester"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CalendarState {
+    kind: Kind,
+    name: String,
+    from: String,
+    to: String,
+    path: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum CalendarMsg {
+    Kind(Kind),
+    Name(String),
+    From(String),
+    To(String),
+    Add,
+    RemoveTerm(usize),
+    RemoveDayOff(usize),
+    Path(String),
+    Browse,
+    Picked(Option<String>),
+    Import,
+    Read(Result<String, String>),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn pick_file() -> Option<String> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("iCalendar", &["ics"])
+        .pick_file()
+        .await
+        .map(|f| f.path().to_string_lossy().to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn pick_file() -> Option<String> {
+    None
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s.trim(), "%d.%m.%Y")
+        .map_err(|_| format!("\"{}\" ist kein Datum (TT.MM.JJJJ)", s))
+}
+
+pub fn update_calendar(app: &mut App, msg: CalendarMsg) -> Task<Message> {
+    match msg {
+        CalendarMsg::Kind(k) => app.calendar.kind = k,
+        CalendarMsg::Name(s) => app.calendar.name = s,
+        CalendarMsg::From(s) => app.calendar.from = s,
+        CalendarMsg::To(s) => app.calendar.to = s,
+        CalendarMsg::Add => {
+            let form = app.calendar.clone();
+            let from = match parse_date(&form.from) {
+                Ok(d) => d,
+                Err(e) => {
+                    app.notify(Toast::new("Nicht hinzugefügt", &e, Status::Danger));
+                    return Task::none();
+                }
+            };
+            // A single day needs no end
+            let to = match form.to.trim() {
+                "" => Ok(from),
+                s => parse_date(s),
+            };
+            let to = match to {
+                Ok(d) if d >= from => d,
+                Ok(_) => {
+                    app.notify(Toast::new(
+                        "Nicht hinzugefügt",
+                        "Das Ende liegt vor dem Anfang",
+                        Status::Danger,
+                    ));
+                    return Task::none();
+                }
+                Err(e) => {
+                    app.notify(Toast::new("Nicht hinzugefügt", &e, Status::Danger));
+                    return Task::none();
+                }
+            };
+            let name = match (form.name.trim(), form.kind) {
+                ("", Kind::Term) => "Semester".to_string(),
+                ("", Kind::DayOff) => "Freier Tag".to_string(),
+                (name, _) => name.to_string(),
+            };
+            let calendar = &mut app.settings.calendar;
+            match form.kind {
+                Kind::Term => calendar.terms.push(Term { name, from, to }),
+                Kind::DayOff => calendar.days_off.push(DayOff { name, from, to }),
+            }
+            calendar.sort();
+            app.mark_changed();
+            app.calendar = CalendarState {
+                kind: form.kind,
+                path: form.path,
+                ..CalendarState::default()
+            };
+        }
+        CalendarMsg::RemoveTerm(i) => {
+            if i < app.settings.calendar.terms.len() {
+                app.settings.calendar.terms.remove(i);
+                app.mark_changed();
+            }
+        }
+        CalendarMsg::RemoveDayOff(i) => {
+            if i < app.settings.calendar.days_off.len() {
+                app.settings.calendar.days_off.remove(i);
+                app.mark_changed();
+            }
+        }
+        CalendarMsg::Path(p) => app.calendar.path = p,
+        CalendarMsg::Browse => {
+            return Task::perform(pick_file(), |p| Message::Calendar(CalendarMsg::Picked(p)))
+        }
+        CalendarMsg::Picked(Some(p)) => {
+            app.calendar.path = p;
+            return Task::done(Message::Calendar(CalendarMsg::Import));
+        }
+        CalendarMsg::Picked(None) => (),
+        CalendarMsg::Import => {
+            let path = app.calendar.path.trim().to_string();
+            if path.is_empty() {
+                return Task::none();
+            }
+            return Task::perform(
+                async move { std::fs::read_to_string(&path).map_err(|e| e.to_string()) },
+                |r| Message::Calendar(CalendarMsg::Read(r)),
+            );
+        }
+        CalendarMsg::Read(Ok(ics)) => match import_ics(&mut app.settings.calendar, &ics) {
+            Ok(added) => {
+                if added.terms + added.days_off > 0 {
+                    app.mark_changed();
+                }
+                let mut message = format!(
+                    "{} Semester und {} freie Tage hinzugefügt",
+                    added.terms, added.days_off
+                );
+                if added.timed > 0 {
+                    message += &format!(", {} Termine mit Uhrzeit übersprungen", added.timed);
+                }
+                app.notify(Toast::new("Kalender importiert", &message, Status::Success));
+            }
+            Err(e) => app.notify(Toast::new("Nicht importiert", &e, Status::Danger)),
+        },
+        CalendarMsg::Read(Err(e)) => app.notify(Toast::new("Nicht importiert", &e, Status::Danger)),
+    }
+    Task::none()
+}
+
+fn range_row<'a>(
+    name: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+    remove: Message,
+) -> Element<'a, Message> {
+    let dates = match from == to {
+        true => from.format("%d.%m.%Y").to_string(),
+        false => format!("{} bis {}", from.format("%d.%m.%Y"), to.format("%d.%m.%Y")),
+    };
+    row![
+        text(name.to_string()).width(200),
+        text(dates).style(themes::text_fg_sec).width(220),
+        button(
+            text(icon_to_string(Bootstrap::TrashthreeFill))
+                .font(ICON_FONT)
+                .style(themes::text_fg_danger)
+        )
+        .on_press(remove)
+        .style(button::text),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center)
+    .into()
+}
+
+/// School calendar section of the settings
+pub fn calendar_view(app: &App) -> Element<Message> {
+    let calendar = &app.settings.calendar;
+    let mut terms = column![text("Semester").style(themes::text_fg)].spacing(2);
+    for (i, t) in calendar.terms.iter().enumerate() {
+        terms = terms.push(range_row(
+            &t.name,
+            t.from,
+            t.to,
+            Message::Calendar(CalendarMsg::RemoveTerm(i)),
+        ));
+    }
+    let mut days_off = column![text("Ferien und freie Tage").style(themes::text_fg)].spacing(2);
+    for (i, d) in calendar.days_off.iter().enumerate() {
+        days_off = days_off.push(range_row(
+            &d.name,
+            d.from,
+            d.to,
+            Message::Calendar(CalendarMsg::RemoveDayOff(i)),
+        ));
+    }
+
+    let form = row![
+        pick_list(Kind::all(), Some(app.calendar.kind), |k| {
+            Message::Calendar(CalendarMsg::Kind(k))
+        }),
+        text_input("Bezeichnung", &app.calendar.name)
+            .on_input(|s| Message::Calendar(CalendarMsg::Name(s))),
+        text_input("Von (TT.MM.JJJJ)", &app.calendar.from)
+            .on_input(|s| Message::Calendar(CalendarMsg::From(s)))
+            .width(140),
+        text_input("Bis", &app.calendar.to)
+            .on_input(|s| Message::Calendar(CalendarMsg::To(s)))
+            .on_submit(Message::Calendar(CalendarMsg::Add))
+            .width(140),
+        styled_button(
+            Bootstrap::PlusSquareFill,
+            "Hinzufügen",
+            Message::Calendar(CalendarMsg::Add),
+            ColorType::Succes,
+        ),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+
+    let import = row![
+        text_input("Pfad zur .ics-Datei", &app.calendar.path)
+            .on_input(|s| Message::Calendar(CalendarMsg::Path(s)))
+            .on_submit(Message::Calendar(CalendarMsg::Import)),
+        styled_button(
+            Bootstrap::Folder,
+            "Durchsuchen",
+            Message::Calendar(CalendarMsg::Browse),
+            ColorType::Secondary,
+        ),
+        styled_button(
+            Bootstrap::CalendarPlusFill,
+            "Importieren",
+            Message::Calendar(CalendarMsg::Import),
+            ColorType::Primary,
+        ),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+
+    column![
+        text("Schulkalender").size(22).style(themes::text_fg),
+        text(
+            "Wochenenden, Ferien und freie Tage werden beim Blättern durch die Daten übersprungen."
+        )
+        .style(themes::text_fg_sec),
+        form,
+        import,
+        terms,
+        days_off,
+    ]
+    .spacing(5)
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Utc};
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn ics(events: &str) -> String {
+        format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{}END:VCALENDAR\r\n",
+            events.replace('\n', "\r\n")
+        )
+    }
+
+    fn event(name: &str, from: NaiveDate, to: NaiveDate, all_day: bool) -> IcsEvent {
+        IcsEvent {
+            name: name.to_string(),
+            from,
+            to,
+            all_day,
+        }
+    }
+
+    fn parse(file: &str) -> Vec<IcsEvent> {
+        parse_ics(file, &Utc).unwrap()
+    }
+
+    #[test]
+    fn whole_day_ends_on_the_day_before_dtend() {
+        let file = ics("BEGIN:VEVENT\n\
+             SUMMARY:Auffahrt\n\
+             DTSTART;VALUE=DATE:20240509\n\
+             DTEND;VALUE=DATE:20240510\n\
+             END:VEVENT\n");
+        assert_eq!(
+            parse(&file),
+            vec![event("Auffahrt", day(2024, 5, 9), day(2024, 5, 9), true)]
+        );
+    }
+
+    #[test]
+    fn several_whole_days() {
+        let file = ics("BEGIN:VEVENT\n\
+             SUMMARY:Sportferien\n\
+             DTSTART:20240212\n\
+             DTEND:20240226\n\
+             END:VEVENT\n");
+        assert_eq!(
+            parse(&file),
+            vec![event(
+                "Sportferien",
+                day(2024, 2, 12),
+                day(2024, 2, 25),
+                true
+            )]
+        );
+    }
+
+    #[test]
+    fn folded_lines_are_joined() {
+        let file = ics("BEGIN:VEVENT\n\
+             SUMMARY:Herbst\n \
+             ferien\\, 2. Woche\n\
+             DTSTART;VALUE=\n\tDATE:20241014\n\
+             DTEND;VALUE=DATE:20241019\n\
+             END:VEVENT\n");
+        assert_eq!(
+            parse(&file),
+            vec![event(
+                "Herbstferien, 2. Woche",
+                day(2024, 10, 14),
+                day(2024, 10, 18),
+                true
+            )]
+        );
+    }
+
+    #[test]
+    fn timed_events_end_before_dtend() {
+        let file = ics("BEGIN:VEVENT\n\
+             SUMMARY:Sporttag\n\
+             DTSTART;VALUE=DATE-TIME:20240612T080000\n\
+             DTEND;VALUE=DATE-TIME:20240613T000000\n\
+             END:VEVENT\n\
+             BEGIN:VEVENT\n\
+             SUMMARY:Elternabend\n\
+             DTSTART;TZID=Europe/Zurich:20240320T190000\n\
+             DTEND;TZID=Europe/Zurich:20240320T210000\n\
+             END:VEVENT\n");
+        assert_eq!(
+            parse(&file),
+            vec![
+                event("Sporttag", day(2024, 6, 12), day(2024, 6, 12), false),
+                event("Elternabend", day(2024, 3, 20), day(2024, 3, 20), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn utc_times_fall_on_the_day_of_the_time_zone() {
+        let file = ics("BEGIN:VEVENT\n\
+             SUMMARY:Konferenz\n\
+             DTSTART:20240415T200000Z\n\
+             DTEND:20240415T220000Z\n\
+             END:VEVENT\n");
+        assert_eq!(
+            parse(&file),
+            vec![event(
+                "Konferenz",
+                day(2024, 4, 15),
+                day(2024, 4, 15),
+                false
+            )]
+        );
+        let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+        assert_eq!(
+            parse_ics(&file, &tokyo).unwrap(),
+            vec![event(
+                "Konferenz",
+                day(2024, 4, 16),
+                day(2024, 4, 16),
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn timed_events_are_no_days_off() {
+        let file = ics("BEGIN:VEVENT\n\
+             SUMMARY:Elternabend\n\
+             DTSTART:20240320T190000\n\
+             DTEND:20240320T210000\n\
+             END:VEVENT\n\
+             BEGIN:VEVENT\n\
+             SUMMARY:Auffahrt\n\
+             DTSTART;VALUE=DATE:20240509\n\
+             DTEND;VALUE=DATE:20240511\n\
+             END:VEVENT\n\
+             BEGIN:VEVENT\n\
+             SUMMARY:1. Semester\n\
+             DTSTART;VALUE=DATE:20240819\n\
+             DTEND;VALUE=DATE:20250201\n\
+             END:VEVENT\n");
+        let mut calendar = Calendar::default();
+        assert_eq!(
+            import_ics(&mut calendar, &file).unwrap(),
+            Imported {
+                terms: 1,
+                days_off: 1,
+                timed: 1
+            }
+        );
+        assert_eq!(
+            calendar.days_off,
+            vec![DayOff {
+                name: "Auffahrt".to_string(),
+                from: day(2024, 5, 9),
+                to: day(2024, 5, 10),
+            }]
+        );
+        assert!(calendar.is_school_day(day(2024, 3, 20)));
+        assert_eq!(calendar.terms[0].to, day(2025, 1, 31));
+    }
+
+    #[test]
+    fn other_files_are_refused() {
+        assert!(parse_ics("SUMMARY:Ferien", &Utc).is_err());
+    }
+}
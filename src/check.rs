@@ -0,0 +1,328 @@
+use crate::bootstrap::*;
+use crate::calendar::Calendar;
+use crate::db::{Change, DataBase, Entry, LessonAbs, StudentId};
+use crate::themes::{self, styled_button, ColorType};
+use crate::schedule::Schedules;
+use crate::time;
+use crate::{App, Message};
+use iced::widget::{button, column, horizontal_rule, horizontal_space, row, scrollable, text};
+use iced::{Alignment, Element, Length, Task};
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "Hinweis"),
+            Severity::Warning => write!(f, "Warnung"),
+            Severity::Error => write!(f, "Fehler"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub text: String,
+    /// Button label and the change that repairs the problem
+    pub fix: Option<(String, Change)>,
+    /// The fix only removes what is certainly wrong and may be applied
+    /// together with the others
+    pub safe: bool,
+}
+
+impl Finding {
+    fn new(severity: Severity, text: String) -> Self {
+        Finding {
+            severity,
+            text,
+            fix: None,
+            safe: false,
+        }
+    }
+    fn fix(self, label: &str, change: Change, safe: bool) -> Self {
+        Finding {
+            fix: Some((label.to_string(), change)),
+            safe,
+            ..self
+        }
+    }
+}
+
+/// Everything that is the same about two entries apart from the id
+fn entry_key(e: &Entry) -> String {
+    format!(
+        "{}|{}|{}|{}|{}|{}",
+        e.person, e.lesson, e.date, e.lesson_time, e.delay_min, e.first_lesson
+    )
+}
+
+/// Looks for records a hand edited or merged file can get wrong. Lesson
+/// times are checked against the bell schedule of their day, dates against
+/// the school calendar. The most severe findings come first.
+pub fn check(db: &DataBase, schedules: &Schedules, calendar: &Calendar) -> Vec<Finding> {
+    let mut findings = vec![];
+    let today = time::get_today();
+
+    let mut ids: BTreeMap<u64, usize> = BTreeMap::new();
+    for e in &db.data {
+        *ids.entry(e.id).or_default() += 1;
+    }
+    for (id, n) in ids.iter().filter(|(_, n)| **n > 1) {
+        findings.push(Finding::new(
+            Severity::Error,
+            format!("Die Id {} wird von {} Verspätungen benutzt", id, n),
+        ));
+    }
+    let unique = |id: u64| ids.get(&id) == Some(&1);
+
+    let mut seen: HashSet<String> = HashSet::new();
+    for e in &db.data {
+        let what = format!(
+            "Verspätung von {} am {}",
+            db.student_name(e.person),
+            e.date.format("%d.%m.%Y")
+        );
+        if db.student(e.person).is_none() {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("{}: Person #{} gibt es nicht", what, e.person),
+            ));
+        }
+        if db.subject(e.lesson).is_none() {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("{}: Fach #{} gibt es nicht", what, e.lesson),
+            ));
+        }
+        if e.date > today {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{} liegt in der Zukunft", what),
+            ));
+        }
+        if let Some(off) = calendar.day_off(e.date) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{} liegt auf einem schulfreien Tag ({})", what, off.name),
+            ));
+        }
+        let snapped = time::snap(schedules, e.date, e.lesson_time).start(schedules);
+        if snapped != e.lesson_time && unique(e.id) {
+            findings.push(
+                Finding::new(
+                    Severity::Warning,
+                    format!(
+                        "{}: {} ist kein Lektionsbeginn",
+                        what,
+                        e.lesson_time.format("%H:%M")
+                    ),
+                )
+                .fix(
+                    &format!("Auf {} setzen", snapped.format("%H:%M")),
+                    Change::UpdateEntry(Entry {
+                        lesson_time: snapped,
+                        ..e.clone()
+                    }),
+                    false,
+                ),
+            );
+        }
+        if !seen.insert(entry_key(e)) && unique(e.id) {
+            findings.push(
+                Finding::new(Severity::Warning, format!("{} ist doppelt erfasst", what))
+                    .fix("Doppelte löschen", Change::DeleteEntry(e.id), true),
+            );
+        }
+    }
+
+    let mut slots: HashSet<String> = HashSet::new();
+    for a in &db.absences {
+        let what = format!(
+            "Absenz vom {} um {}",
+            a.date.format("%d.%m.%Y"),
+            a.lesson_time.format("%H:%M")
+        );
+        if db.cohort(a.cohort).is_none() {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("{}: Klasse #{} gibt es nicht", what, a.cohort),
+            ));
+        }
+        if db.subject(a.lesson).is_none() {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("{}: Fach #{} gibt es nicht", what, a.lesson),
+            ));
+        }
+        if a.date > today {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{} liegt in der Zukunft", what),
+            ));
+        }
+        if let Some(off) = calendar.day_off(a.date) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{} liegt auf einem schulfreien Tag ({})", what, off.name),
+            ));
+        }
+        if !slots.insert(format!("{}|{}|{}", a.cohort, a.date, a.lesson_time)) {
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("{} ist für diese Klasse mehrfach erfasst", what),
+            ));
+        }
+        // Possible for a class trip, but more often a forgotten list
+        if a.present.is_empty() && a.archived_absent.is_none() {
+            findings.push(Finding::new(
+                Severity::Info,
+                format!("{}: Niemand war anwesend", what),
+            ));
+        }
+
+        let mut problems = vec![];
+        let mut present: Vec<StudentId> = vec![];
+        for &p in &a.present {
+            if present.contains(&p) {
+                problems.push(format!("{} doppelt", db.student_name(p)));
+            } else if db.student(p).is_none() {
+                problems.push(format!("Person #{} unbekannt", p));
+            } else if !db.in_cohort(p, Some(a.cohort)) {
+                problems.push(format!("{} aus einer anderen Klasse", db.student_name(p)));
+            } else {
+                present.push(p);
+            }
+        }
+        if !problems.is_empty() {
+            findings.push(
+                Finding::new(
+                    Severity::Warning,
+                    format!("{}, Anwesende: {}", what, problems.join(", ")),
+                )
+                .fix(
+                    "Liste bereinigen",
+                    Change::UpdateAbsence(LessonAbs {
+                        present,
+                        ..a.clone()
+                    }),
+                    true,
+                ),
+            );
+        }
+    }
+
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+    findings
+}
+
+#[derive(Debug, Clone)]
+pub enum CheckMsg {
+    Fix(usize),
+    FixAll,
+}
+
+pub fn update_check(app: &mut App, msg: CheckMsg) -> Task<Message> {
+    let findings = check(&app.db, &app.settings.schedules, &app.settings.calendar);
+    match msg {
+        CheckMsg::Fix(i) => {
+            if let Some((label, change)) = findings.into_iter().nth(i).and_then(|f| f.fix) {
+                app.commit_undoable(change, "Korrigiert", &label);
+            }
+        }
+        CheckMsg::FixAll => {
+            let changes: Vec<Change> = findings
+                .into_iter()
+                .filter(|f| f.safe)
+                .filter_map(|f| f.fix)
+                .map(|(_, change)| change)
+                .collect();
+            if !changes.is_empty() {
+                let n = changes.len();
+                app.commit_undoable(
+                    Change::Batch(changes),
+                    "Korrigiert",
+                    &format!("{} sichere Korrekturen angewendet", n),
+                );
+            }
+        }
+    }
+    Task::none()
+}
+
+pub fn check_view(app: &App) -> Element<Message> {
+    let findings = check(&app.db, &app.settings.schedules, &app.settings.calendar);
+    let safe = findings.iter().filter(|f| f.safe).count();
+
+    let mut list = column![].spacing(5);
+    for (i, f) in findings.iter().enumerate() {
+        let (icon, style): (Bootstrap, fn(&iced::Theme) -> text::Style) = match f.severity {
+            Severity::Error => (Bootstrap::ExclamationOctagonFill, themes::text_fg_danger),
+            Severity::Warning => (Bootstrap::ExclamationTriangleFill, themes::text_fg_danger),
+            Severity::Info => (Bootstrap::InfoCircleFill, themes::text_fg_sec),
+        };
+        let mut line = row![
+            text(icon_to_string(icon)).font(ICON_FONT).style(style),
+            text(f.severity.to_string()).style(style).width(70),
+            text(f.text.clone()).width(Length::Fill),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        if let Some((label, _)) = &f.fix {
+            line = line.push(
+                button(text(label.clone()))
+                    .on_press(Message::Check(CheckMsg::Fix(i)))
+                    .style(button::secondary),
+            );
+        }
+        list = list.push(line);
+    }
+    if findings.is_empty() {
+        list = list.push(text("Keine Auffälligkeiten gefunden").style(themes::text_fg_succes));
+    }
+
+    let mut header = row![
+        button(
+            row![
+                text(icon_to_string(Bootstrap::ArrowLeftSquareFill))
+                    .font(ICON_FONT)
+                    .style(themes::text_fg)
+                    .size(22),
+                text("Zurück").style(themes::text_fg).size(20)
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+        )
+        .on_press(Message::BackView)
+        .style(button::text),
+        text("Datenprüfung").size(22).style(themes::text_fg),
+        horizontal_space(),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    if safe > 0 {
+        header = header
+            .push(text(format!("{} sicher korrigierbar", safe)).style(themes::text_fg_sec))
+            .push(styled_button(
+                Bootstrap::CheckSquareFill,
+                "Alle korrigieren",
+                Message::Check(CheckMsg::FixAll),
+                ColorType::Succes,
+            ));
+    }
+
+    column![
+        header,
+        horizontal_rule(1),
+        scrollable(list).style(themes::scrollbar_invis),
+    ]
+    .spacing(5)
+    .padding(20)
+    .into()
+}
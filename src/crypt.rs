@@ -0,0 +1,134 @@
+use crate::db::DataBaseError;
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use zeroize::Zeroizing;
+
+const FORMAT: &str = "zuspaet-encrypted";
+
+/// What an encrypted file looks like on disk. The salt belongs to the
+/// passphrase, so files written with an older passphrase still open as
+/// long as that passphrase is given.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    format: String,
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+struct Session {
+    passphrase: Zeroizing<String>,
+    keys: HashMap<[u8; 16], Zeroizing<[u8; 32]>>,
+    /// Salt of the key new files are written with
+    write_salt: Option<[u8; 16]>,
+}
+
+/// The passphrase of the running program, `None` while nothing is encrypted
+static SESSION: Mutex<Option<Session>> = Mutex::new(None);
+
+fn session() -> MutexGuard<'static, Option<Session>> {
+    SESSION.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Session {
+    fn key(&mut self, salt: [u8; 16]) -> Result<XChaCha20Poly1305, DataBaseError> {
+        if !self.keys.contains_key(&salt) {
+            let mut key = Zeroizing::new([0u8; 32]);
+            Argon2::default()
+                .hash_password_into(self.passphrase.as_bytes(), &salt, key.as_mut())
+                .map_err(|e| DataBaseError::Other(format!("Schlüssel: {}", e)))?;
+            self.keys.insert(salt, key);
+        }
+        Ok(XChaCha20Poly1305::new(Key::from_slice(self.keys[&salt].as_ref())))
+    }
+}
+
+/// Uses `passphrase` for everything read and written from now on. Files
+/// keep their salt, new files get the one of the first file opened.
+pub fn unlock(passphrase: &str) {
+    *session() = Some(Session {
+        passphrase: Zeroizing::new(passphrase.to_string()),
+        keys: HashMap::new(),
+        write_salt: None,
+    });
+}
+
+/// Like `unlock`, but with a fresh salt for a new or changed passphrase
+pub fn set_passphrase(passphrase: &str) {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    *session() = Some(Session {
+        passphrase: Zeroizing::new(passphrase.to_string()),
+        keys: HashMap::new(),
+        write_salt: Some(salt),
+    });
+}
+
+/// Forgets the passphrase, files are written in plain text again
+pub fn lock() {
+    *session() = None;
+}
+
+pub fn active() -> bool {
+    session().is_some()
+}
+
+/// Encrypts `plain` if a passphrase is set, otherwise returns it unchanged
+pub fn seal(plain: &str) -> Result<String, DataBaseError> {
+    let mut guard = session();
+    let Some(session) = guard.as_mut() else {
+        return Ok(plain.to_string());
+    };
+    let salt = *session.write_salt.get_or_insert_with(|| {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        salt
+    });
+    let cipher = session.key(salt)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plain.as_bytes())
+        .map_err(|_| DataBaseError::Other("Verschlüsselung fehlgeschlagen".to_string()))?;
+    let envelope = Envelope {
+        format: FORMAT.to_string(),
+        kdf: "argon2id".to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    };
+    Ok(serde_json::to_string(&envelope)?)
+}
+
+/// Decrypts `text` if it was written by `seal` with a passphrase, plain
+/// text is returned unchanged
+pub fn open(text: &str) -> Result<String, DataBaseError> {
+    let envelope = match serde_json::from_str::<Envelope>(text) {
+        Ok(e) if e.format == FORMAT => e,
+        _ => return Ok(text.to_string()),
+    };
+    let mut guard = session();
+    let Some(session) = guard.as_mut() else {
+        return Err(DataBaseError::Locked);
+    };
+    let decode = |s: &str| STANDARD.decode(s).map_err(|_| DataBaseError::WrongPassphrase);
+    let salt: [u8; 16] = decode(&envelope.salt)?
+        .try_into()
+        .map_err(|_| DataBaseError::WrongPassphrase)?;
+    let nonce = decode(&envelope.nonce)?;
+    if nonce.len() != 24 {
+        return Err(DataBaseError::WrongPassphrase);
+    }
+    let plain = session
+        .key(salt)?
+        .decrypt(XNonce::from_slice(&nonce), decode(&envelope.ciphertext)?.as_ref())
+        .map_err(|_| DataBaseError::WrongPassphrase)?;
+    session.write_salt.get_or_insert(salt);
+    String::from_utf8(plain).map_err(|_| DataBaseError::WrongPassphrase)
+}
@@ -1,28 +1,168 @@
+use crate::calendar::Calendar;
+use crate::crypt;
+use crate::migrate::{self, SCHEMA_VERSION};
 use crate::App;
-use crate::{
-    stats::Ranking,
-    time::{self, get_today},
-};
-use chrono::{NaiveDate, NaiveTime};
+use crate::{stats::Ranking, time};
+use chrono::{NaiveDate, NaiveTime, Weekday};
 use serde_derive::*;
 use std::io::{self, Write};
 
 #[derive(Debug, Clone)]
-pub struct DataBaseError;
+pub enum DataBaseError {
+    /// Reading or writing a file failed
+    Io {
+        kind: io::ErrorKind,
+        path: Option<String>,
+        message: String,
+    },
+    /// The file is no valid JSON or does not look like a database.
+    /// `line` is 0 if the JSON itself was fine.
+    Parse {
+        path: Option<String>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The file was written by a newer version with this schema version
+    UnsupportedVersion(u32),
+    /// Reported by the SQLite backend
+    Sqlite { path: Option<String>, message: String },
+    /// The file is encrypted and no passphrase was given yet
+    Locked,
+    /// Decrypting failed, the passphrase is wrong or the file was altered
+    WrongPassphrase,
+    /// Anything else, the text is shown as is
+    Other(String),
+}
+
+impl DataBaseError {
+    /// Remembers the file the error happened in, unless one is already known
+    pub fn at(self, file: &str) -> Self {
+        match self {
+            DataBaseError::Io {
+                kind,
+                path: None,
+                message,
+            } => DataBaseError::Io {
+                kind,
+                path: Some(file.to_string()),
+                message,
+            },
+            DataBaseError::Parse {
+                path: None,
+                line,
+                column,
+                message,
+            } => DataBaseError::Parse {
+                path: Some(file.to_string()),
+                line,
+                column,
+                message,
+            },
+            DataBaseError::Sqlite {
+                path: None,
+                message,
+            } => DataBaseError::Sqlite {
+                path: Some(file.to_string()),
+                message,
+            },
+            e => e,
+        }
+    }
+
+    /// True if the file simply does not exist yet
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            DataBaseError::Io {
+                kind: io::ErrorKind::NotFound,
+                ..
+            }
+        )
+    }
+}
+
+impl std::fmt::Display for DataBaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DataBaseError::Io {
+                kind,
+                path,
+                message,
+            } => {
+                let path = path.as_deref().unwrap_or("Datei");
+                match kind {
+                    io::ErrorKind::NotFound => write!(f, "{} nicht gefunden", path),
+                    io::ErrorKind::PermissionDenied => {
+                        write!(f, "Keine Berechtigung für {}", path)
+                    }
+                    _ => write!(f, "Fehler beim Zugriff auf {}: {}", path, message),
+                }
+            }
+            DataBaseError::Parse {
+                path,
+                line,
+                column,
+                message,
+            } => {
+                let path = path.as_deref().unwrap_or("Datei");
+                match line {
+                    0 => write!(f, "{} hat ein unerwartetes Format: {}", path, message),
+                    _ => write!(
+                        f,
+                        "{} ist beschädigt (Zeile {}, Spalte {}): {}",
+                        path, line, column, message
+                    ),
+                }
+            }
+            DataBaseError::UnsupportedVersion(v) => write!(
+                f,
+                "Die Datenbank hat Schema-Version {}, dieses Programm kennt nur Versionen bis {}. Bitte das Programm aktualisieren.",
+                v,
+                SCHEMA_VERSION
+            ),
+            DataBaseError::Sqlite { path, message } => match path {
+                Some(path) => write!(f, "SQLite-Fehler in {}: {}", path, message),
+                None => write!(f, "SQLite-Fehler: {}", message),
+            },
+            DataBaseError::Locked => write!(f, "Die Datenbank ist verschlüsselt"),
+            DataBaseError::WrongPassphrase => {
+                write!(f, "Falsches Passwort oder die Datei wurde verändert")
+            }
+            DataBaseError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
 
 impl From<io::Error> for DataBaseError {
-    fn from(_value: io::Error) -> Self {
-        DataBaseError {}
+    fn from(value: io::Error) -> Self {
+        DataBaseError::Io {
+            kind: value.kind(),
+            path: None,
+            message: value.to_string(),
+        }
     }
 }
 impl From<serde_json::Error> for DataBaseError {
-    fn from(_value: serde_json::Error) -> Self {
-        DataBaseError {}
+    fn from(value: serde_json::Error) -> Self {
+        match value.classify() {
+            serde_json::error::Category::Io => DataBaseError::Io {
+                kind: value.io_error_kind().unwrap_or(io::ErrorKind::Other),
+                path: None,
+                message: value.to_string(),
+            },
+            _ => DataBaseError::Parse {
+                path: None,
+                line: value.line(),
+                column: value.column(),
+                message: value.to_string(),
+            },
+        }
     }
 }
 
 pub struct ProfileStats {
-    pub person: Class,
+    pub person: StudentId,
     pub num: u32,
     pub avg_min: f32,
     pub percent: f32,
@@ -35,7 +175,7 @@ pub struct ProfileStats {
 }
 
 impl ProfileStats {
-    pub fn empty(p: Class) -> Self {
+    pub fn empty(p: StudentId) -> Self {
         ProfileStats {
             person: p,
             num: 0,
@@ -51,66 +191,299 @@ impl ProfileStats {
     }
 }
 
+pub type CohortId = u32;
+
+/// A school class with its own roster and subjects
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Cohort {
+    pub id: CohortId,
+    pub name: String,
+}
+
+impl std::fmt::Display for Cohort {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub type StudentId = u32;
+
+/// A student with their number of lates and the minutes they add up to
+pub type Lates = (StudentId, u32, u32);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Student {
+    pub id: StudentId,
+    pub cohort: CohortId,
+    pub first_name: String,
+    pub last_name: String,
+    #[serde(default)]
+    pub alias: Option<String>,
+    /// Stands in for a real person in records archived by the retention
+    /// policy, left out of every roster
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pseudonym: bool,
+}
+
+impl Student {
+    pub fn full_name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+            .trim()
+            .to_string()
+    }
+}
+
+impl std::fmt::Display for Student {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.alias {
+            Some(alias) if !alias.is_empty() => write!(f, "{}", alias),
+            _ => write!(f, "{}", self.full_name()),
+        }
+    }
+}
+
+pub type SubjectId = u32;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Subject {
+    pub id: SubjectId,
+    pub cohort: CohortId,
+    pub name: String,
+    pub code: String,
+    pub color: [u8; 3],
+    /// Archived subjects are hidden from the pickers but stay in the history
+    pub active: bool,
+    /// Where the subject sits in the weekly timetable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub slots: Vec<WeekSlot>,
+}
+
+/// A lesson of the weekly timetable, `slot` indexes the bell schedule
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+pub struct WeekSlot {
+    pub weekday: Weekday,
+    pub slot: usize,
+}
+
+impl Subject {
+    pub fn iced_color(&self) -> iced::Color {
+        iced::Color::from_rgb8(self.color[0], self.color[1], self.color[2])
+    }
+    pub fn hex(&self) -> String {
+        format!(
+            "#{:02x}{:02x}{:02x}",
+            self.color[0], self.color[1], self.color[2]
+        )
+    }
+    pub fn parse_hex(s: &str) -> Option<[u8; 3]> {
+        let s = s.trim().trim_start_matches('#');
+        if s.len() != 6 || !s.is_ascii() {
+            return None;
+        }
+        let c = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
+        Some([c(0)?, c(2)?, c(4)?])
+    }
+}
+
+impl std::fmt::Display for Subject {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+pub type EntryId = u64;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct Entry {
-    pub person: Class,
-    pub lesson: Lesson,
+    pub id: EntryId,
+    pub person: StudentId,
+    pub lesson: SubjectId,
     pub lesson_time: NaiveTime,
     pub delay_min: u32,
     pub first_lesson: bool,
     pub date: NaiveDate,
+    #[serde(default)]
+    pub reason: Reason,
+    #[serde(default)]
+    pub note: String,
+    /// Excused lates don't count towards penalties
+    #[serde(default)]
+    pub excused: bool,
 }
 impl Entry {
-    pub fn empty() -> Entry {
+    pub fn empty(date: NaiveDate, lesson_time: NaiveTime) -> Entry {
         Entry {
-            person: Class::Marie,
-            lesson: Lesson::BG,
-            lesson_time: time::get_last_lesson(),
+            id: 0,
+            person: 0,
+            lesson: 0,
+            lesson_time,
             delay_min: 0,
             first_lesson: false,
-            date: get_today(),
+            date,
+            reason: Reason::default(),
+            note: String::new(),
+            excused: false,
         }
     }
 }
-#[derive(Debug, Serialize, Deserialize, Clone)]
+
+/// Why someone was late
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Default)]
+pub enum Reason {
+    #[default]
+    Unknown,
+    Transport,
+    Overslept,
+    Appointment,
+    Illness,
+    Other,
+}
+
+impl Reason {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Reason::Unknown,
+            Reason::Transport,
+            Reason::Overslept,
+            Reason::Appointment,
+            Reason::Illness,
+            Reason::Other,
+        ]
+    }
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Reason::Unknown => write!(f, "Kein Grund"),
+            Reason::Transport => write!(f, "ÖV / Verkehr"),
+            Reason::Overslept => write!(f, "Verschlafen"),
+            Reason::Appointment => write!(f, "Termin"),
+            Reason::Illness => write!(f, "Krankheit"),
+            Reason::Other => write!(f, "Anderes"),
+        }
+    }
+}
+pub type AbsenceId = u64;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct LessonAbs {
-    pub present: Vec<Class>,
-    pub lesson: Lesson,
+    pub id: AbsenceId,
+    pub cohort: CohortId,
+    pub present: Vec<StudentId>,
+    pub lesson: SubjectId,
     pub lesson_time: NaiveTime,
     pub first_lesson: bool,
     pub date: NaiveDate,
+    /// Number of absent students once the names were removed by the
+    /// retention policy, `present` is empty then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived_absent: Option<u32>,
+    /// One per missing student, created when the absence is recorded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<AbsenceRecord>,
+    /// The multi-day absence this lesson was created for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<SpanId>,
+}
+
+pub type SpanId = u64;
+
+/// Where the excuse for a missed lesson stands
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Default)]
+pub enum ExcuseStatus {
+    #[default]
+    Open,
+    Excused,
+    Unexcused,
 }
 
-impl Default for LessonAbs {
-    fn default() -> Self {
-        LessonAbs::new()
+impl std::fmt::Display for ExcuseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExcuseStatus::Open => write!(f, "offen"),
+            ExcuseStatus::Excused => write!(f, "entschuldigt"),
+            ExcuseStatus::Unexcused => write!(f, "unentschuldigt"),
+        }
     }
 }
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct AbsenceRecord {
+    pub student: StudentId,
+    pub status: ExcuseStatus,
+    #[serde(default)]
+    pub note: String,
+    /// Last day to hand in the excuse
+    pub deadline: NaiveDate,
+    /// Set if the record belongs to a multi-day absence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<SpanId>,
+}
+
 impl LessonAbs {
-    pub fn new() -> Self {
+    pub fn new(date: NaiveDate, lesson_time: NaiveTime) -> Self {
         LessonAbs {
-            present: Class::all(),
-            lesson: Lesson::BG,
-            lesson_time: time::get_last_lesson(),
+            id: 0,
+            cohort: 0,
+            present: vec![],
+            lesson: 0,
+            lesson_time,
             first_lesson: false,
-            date: get_today(),
+            date,
+            archived_absent: None,
+            records: vec![],
+            span: None,
         }
     }
-    pub fn new_smart(absences: &Vec<LessonAbs>) -> Self {
+    pub fn new_smart(
+        db: &DataBase,
+        cohort: Option<CohortId>,
+        date: NaiveDate,
+        lesson_time: NaiveTime,
+    ) -> Self {
         let mut l = LessonAbs {
-            present: Class::all(),
-            lesson: Lesson::BG,
-            lesson_time: time::get_last_lesson(),
+            id: 0,
+            cohort: cohort.unwrap_or(0),
+            present: db.roster(cohort).iter().map(|s| s.id).collect(),
+            lesson: db
+                .active_subjects(cohort)
+                .first()
+                .map(|s| s.id)
+                .unwrap_or(0),
+            lesson_time,
             first_lesson: false,
-            date: get_today(),
+            date,
+            archived_absent: None,
+            records: vec![],
+            span: None,
         };
-        if absences.len() > 0 {
-            l.present = absences[absences.len() - 1].present.clone();
-            l.lesson = absences[absences.len() - 1].lesson.clone();
+        if let Some(last) = db
+            .absences
+            .iter()
+            .rev()
+            .find(|a| Some(a.cohort) == cohort && a.archived_absent.is_none())
+        {
+            l.present = last.present.clone();
+            l.lesson = last.lesson;
         }
         l
     }
-    pub fn toggle_person(&mut self, person: Class) {
+    pub fn record(&self, student: StudentId) -> Option<&AbsenceRecord> {
+        self.records.iter().find(|r| r.student == student)
+    }
+    /// Students recorded missing, whoever is in the class today
+    pub fn absentees(&self) -> Vec<StudentId> {
+        self.records.iter().map(|r| r.student).collect()
+    }
+    /// Students missing, archived absences only know how many
+    pub fn absent_count(&self) -> usize {
+        match self.archived_absent {
+            Some(n) => n as usize,
+            None => self.records.len(),
+        }
+    }
+    pub fn toggle_person(&mut self, person: StudentId) {
         if self.present.contains(&person) {
             self.present.retain(|p| *p != person);
         } else {
@@ -119,44 +492,370 @@ impl LessonAbs {
     }
 }
 
+/// One modification of the database. Every change goes through
+/// `DataBase::apply` so storage backends can persist it on its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Change {
+    AddEntry(Entry),
+    UpdateEntry(Entry),
+    DeleteEntry(EntryId),
+    AddAbsence(LessonAbs),
+    UpdateAbsence(LessonAbs),
+    DeleteAbsence(AbsenceId),
+    SetCohorts(Vec<Cohort>),
+    SetStudents(Vec<Student>),
+    SetSubjects(Vec<Subject>),
+    /// Swaps the whole database, e.g. after a reset
+    Replace(Box<DataBase>),
+    /// Changes that are undone and redone as one, in order. Storage writes
+    /// them one by one, so they should not contain a `Replace`.
+    Batch(Vec<Change>),
+}
+
+impl Change {
+    /// Short description for the undo and redo toasts
+    pub fn label(&self) -> &'static str {
+        match self {
+            Change::AddEntry(_) => "Verspätung erfasst",
+            Change::UpdateEntry(_) => "Verspätung bearbeitet",
+            Change::DeleteEntry(_) => "Verspätung gelöscht",
+            Change::AddAbsence(_) => "Absenz erfasst",
+            Change::UpdateAbsence(_) => "Absenz bearbeitet",
+            Change::DeleteAbsence(_) => "Absenz gelöscht",
+            Change::SetCohorts(_) => "Klassen geändert",
+            Change::SetStudents(_) => "Schülerliste geändert",
+            Change::SetSubjects(_) => "Fächer geändert",
+            Change::Replace(_) => "Datenbank ersetzt",
+            Change::Batch(_) => "Mehrere Änderungen",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataBase {
+    pub schema_version: u32,
+    pub cohorts: Vec<Cohort>,
+    pub students: Vec<Student>,
+    pub subjects: Vec<Subject>,
     pub data: Vec<Entry>,
     pub absences: Vec<LessonAbs>,
 }
 impl DataBase {
     pub fn empty() -> DataBase {
         DataBase {
+            schema_version: SCHEMA_VERSION,
+            cohorts: vec![],
+            students: vec![],
+            subjects: vec![],
             data: vec![],
             absences: vec![],
         }
     }
-    pub async fn load_file(path: &str) -> Result<DataBase, DataBaseError> {
-        let content = std::fs::read_to_string(path)?;
-        let lib: DataBase = serde_json::from_str(&content)?;
+    /// Loads `path`, falling back to the copy kept by the last save if the
+    /// file is missing or broken. The flag is true if the fallback was used.
+    pub fn read_or_recover(path: &str) -> Result<(DataBase, bool), DataBaseError> {
+        match DataBase::read_file(path) {
+            Ok(db) => Ok((db, false)),
+            // The fallback would not help here, and must not hide these
+            Err(
+                e @ (DataBaseError::UnsupportedVersion(_)
+                | DataBaseError::Locked
+                | DataBaseError::WrongPassphrase),
+            ) => Err(e),
+            Err(e) => match DataBase::read_file(&prev_path(path)) {
+                Ok(db) => Ok((db, true)),
+                Err(_) => Err(e),
+            },
+        }
+    }
+    pub fn read_file(path: &str) -> Result<DataBase, DataBaseError> {
+        DataBase::read_file_inner(path).map_err(|e| e.at(path))
+    }
+    fn read_file_inner(path: &str) -> Result<DataBase, DataBaseError> {
+        let content = crypt::open(&std::fs::read_to_string(path)?)?;
+        let mut doc: serde_json::Value = serde_json::from_str(&content)?;
+        let version = migrate::version_of(&doc);
+        if version < SCHEMA_VERSION {
+            // Keep the file as it was before touching it, sealed again so an
+            // old plain file doesn't stay readable once a passphrase is set
+            let bak = format!("{}.v{}.bak", path, version);
+            std::fs::write(&bak, crypt::seal(&content)?)
+                .map_err(|e| DataBaseError::from(e).at(&bak))?;
+        }
+        migrate::migrate(&mut doc)?;
+        let lib: DataBase = serde_json::from_value(doc)?;
         Ok(lib)
     }
-    pub async fn save_file(self, path: String) -> Result<(), DataBaseError> {
-        let json_db = serde_json::to_string_pretty(&self)?;
-        let r = std::fs::remove_file(&path);
-        match r {
-            Ok(_val) => (),
-            _err => (),
+    /// Reads `path` without writing anything: migrated in memory only, with
+    /// no backup of the old version and no fallback to `<path>.prev`
+    pub fn read_only(path: &str) -> Result<DataBase, DataBaseError> {
+        DataBase::read_only_inner(path).map_err(|e| e.at(path))
+    }
+    fn read_only_inner(path: &str) -> Result<DataBase, DataBaseError> {
+        let content = crypt::open(&std::fs::read_to_string(path)?)?;
+        let mut doc: serde_json::Value = serde_json::from_str(&content)?;
+        migrate::migrate(&mut doc)?;
+        Ok(serde_json::from_value(doc)?)
+    }
+    /// Writes to a temporary file first and renames it over `path`, so a
+    /// failed save never leaves a half written database behind. The file
+    /// being replaced is kept as `<path>.prev` if it is still readable.
+    pub fn write_file(&self, path: &str) -> Result<(), DataBaseError> {
+        self.write_file_inner(path).map_err(|e| e.at(path))
+    }
+    fn write_file_inner(&self, path: &str) -> Result<(), DataBaseError> {
+        let json_db = crypt::seal(&serde_json::to_string_pretty(self)?)?;
+        let tmp = format!("{}.tmp", path);
+        {
+            let mut file = std::fs::File::create(&tmp)?;
+            file.write_all(json_db.as_bytes())?;
+            file.sync_all()?;
+        }
+        let current_ok = std::fs::read_to_string(path)
+            .ok()
+            .is_some_and(|c| serde_json::from_str::<serde_json::Value>(&c).is_ok());
+        if current_ok {
+            std::fs::copy(path, prev_path(path))?;
         }
-        let mut file = std::fs::File::create(&path)?;
-        file.write_all(json_db.as_bytes())?;
+        std::fs::rename(&tmp, path)?;
         Ok(())
     }
 
+    /// Copy of the database restricted to one class, `None` keeps all classes
+    pub fn scoped(&self, cohort: Option<CohortId>) -> DataBase {
+        let Some(cohort) = cohort else {
+            return self.clone();
+        };
+        let students: Vec<Student> = self
+            .students
+            .iter()
+            .filter(|s| s.cohort == cohort)
+            .cloned()
+            .collect();
+        DataBase {
+            schema_version: self.schema_version,
+            cohorts: self
+                .cohorts
+                .iter()
+                .filter(|c| c.id == cohort)
+                .cloned()
+                .collect(),
+            subjects: self
+                .subjects
+                .iter()
+                .filter(|s| s.cohort == cohort)
+                .cloned()
+                .collect(),
+            data: self
+                .data
+                .iter()
+                .filter(|e| students.iter().any(|s| s.id == e.person))
+                .cloned()
+                .collect(),
+            absences: self
+                .absences
+                .iter()
+                .filter(|a| a.cohort == cohort)
+                .cloned()
+                .collect(),
+            students,
+        }
+    }
+    pub fn entry(&self, id: EntryId) -> Option<&Entry> {
+        self.data.iter().find(|e| e.id == id)
+    }
+    pub fn next_entry_id(&self) -> EntryId {
+        self.data.iter().map(|e| e.id).max().unwrap_or(0) + 1
+    }
+    pub fn absence(&self, id: AbsenceId) -> Option<&LessonAbs> {
+        self.absences.iter().find(|a| a.id == id)
+    }
+    pub fn next_absence_id(&self) -> AbsenceId {
+        self.absences.iter().map(|a| a.id).max().unwrap_or(0) + 1
+    }
+
+    pub fn apply(&mut self, change: Change) {
+        match change {
+            // Adding an id that exists replaces it, so replaying the journal
+            // over a database that already has some of its changes is harmless
+            Change::AddEntry(e) => match self.data.iter_mut().find(|o| o.id == e.id) {
+                Some(old) => *old = e,
+                None => self.data.push(e),
+            },
+            Change::UpdateEntry(e) => {
+                if let Some(old) = self.data.iter_mut().find(|o| o.id == e.id) {
+                    *old = e;
+                }
+            }
+            Change::DeleteEntry(id) => self.data.retain(|e| e.id != id),
+            Change::AddAbsence(a) => match self.absences.iter_mut().find(|o| o.id == a.id) {
+                Some(old) => *old = a,
+                None => self.absences.push(a),
+            },
+            Change::UpdateAbsence(a) => {
+                if let Some(old) = self.absences.iter_mut().find(|o| o.id == a.id) {
+                    *old = a;
+                }
+            }
+            Change::DeleteAbsence(id) => self.absences.retain(|a| a.id != id),
+            Change::SetCohorts(c) => self.cohorts = c,
+            Change::SetStudents(s) => self.students = s,
+            Change::SetSubjects(s) => self.subjects = s,
+            Change::Replace(db) => *self = *db,
+            Change::Batch(changes) => {
+                for c in changes {
+                    self.apply(c);
+                }
+            }
+        }
+    }
+
+    /// The change that takes `change` back, computed before it is applied.
+    /// `None` if it would not modify anything.
+    pub fn inverse(&self, change: &Change) -> Option<Change> {
+        Some(match change {
+            Change::AddEntry(e) => Change::DeleteEntry(e.id),
+            Change::UpdateEntry(e) => Change::UpdateEntry(self.entry(e.id)?.clone()),
+            Change::DeleteEntry(id) => Change::AddEntry(self.entry(*id)?.clone()),
+            Change::AddAbsence(a) => Change::DeleteAbsence(a.id),
+            Change::UpdateAbsence(a) => Change::UpdateAbsence(self.absence(a.id)?.clone()),
+            Change::DeleteAbsence(id) => Change::AddAbsence(self.absence(*id)?.clone()),
+            Change::SetCohorts(_) => Change::SetCohorts(self.cohorts.clone()),
+            Change::SetStudents(_) => Change::SetStudents(self.students.clone()),
+            Change::SetSubjects(_) => Change::SetSubjects(self.subjects.clone()),
+            Change::Replace(_) => Change::Replace(Box::new(self.clone())),
+            // Each inverse restores what a record was before the batch, so
+            // played backwards they undo it even if records change twice
+            Change::Batch(changes) => {
+                Change::Batch(changes.iter().rev().filter_map(|c| self.inverse(c)).collect())
+            }
+        })
+    }
+
+    pub fn cohort(&self, id: CohortId) -> Option<&Cohort> {
+        self.cohorts.iter().find(|c| c.id == id)
+    }
+    pub fn next_cohort_id(&self) -> CohortId {
+        self.cohorts.iter().map(|c| c.id).max().unwrap_or(0) + 1
+    }
+    pub fn in_cohort(&self, student: StudentId, cohort: Option<CohortId>) -> bool {
+        match cohort {
+            None => true,
+            Some(c) => self.student(student).is_some_and(|s| s.cohort == c),
+        }
+    }
+
+    /// Students of a class (or of all classes) sorted by the name shown in the pickers
+    pub fn roster(&self, cohort: Option<CohortId>) -> Vec<Student> {
+        let mut r: Vec<Student> = self
+            .students
+            .iter()
+            .filter(|s| !s.pseudonym && cohort.is_none_or(|c| s.cohort == c))
+            .cloned()
+            .collect();
+        r.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
+        r
+    }
+    /// Gives everyone of the class not marked present in `a` an open record
+    /// with a deadline `deadline_days` school days after the lesson. Records
+    /// of students marked present again are dropped, the others are kept as
+    /// they are, also those of students no longer in the class.
+    pub fn sync_records(&self, a: &mut LessonAbs, deadline_days: u32, calendar: &Calendar) {
+        a.records.retain(|r| !a.present.contains(&r.student));
+        for student in self.roster(Some(a.cohort)).iter().map(|s| s.id) {
+            if !a.present.contains(&student) && a.record(student).is_none() {
+                a.records.push(AbsenceRecord {
+                    student,
+                    status: ExcuseStatus::Open,
+                    note: String::new(),
+                    deadline: time::add_school_days(a.date, deadline_days, calendar),
+                    span: None,
+                });
+            }
+        }
+    }
+    pub fn student(&self, id: StudentId) -> Option<&Student> {
+        self.students.iter().find(|s| s.id == id)
+    }
+    pub fn student_name(&self, id: StudentId) -> String {
+        match self.student(id) {
+            Some(s) => s.to_string(),
+            None => format!("Unbekannt #{}", id),
+        }
+    }
+    pub fn next_student_id(&self) -> StudentId {
+        self.students.iter().map(|s| s.id).max().unwrap_or(0) + 1
+    }
+    /// A student can only be removed while nothing refers to them
+    pub fn student_in_use(&self, id: StudentId) -> bool {
+        self.data.iter().any(|e| e.person == id)
+            || self
+                .absences
+                .iter()
+                .any(|a| a.present.contains(&id) || a.record(id).is_some())
+    }
+
+    /// Subjects of a class that are still taught, sorted by name
+    pub fn active_subjects(&self, cohort: Option<CohortId>) -> Vec<Subject> {
+        let mut r: Vec<Subject> = self
+            .subjects
+            .iter()
+            .filter(|s| s.active && cohort.is_none_or(|c| s.cohort == c))
+            .cloned()
+            .collect();
+        r.sort_by(|a, b| a.name.cmp(&b.name));
+        r
+    }
+    pub fn subject(&self, id: SubjectId) -> Option<&Subject> {
+        self.subjects.iter().find(|s| s.id == id)
+    }
+    pub fn subject_name(&self, id: SubjectId) -> String {
+        match self.subject(id) {
+            Some(s) => s.name.clone(),
+            None => format!("Unbekannt #{}", id),
+        }
+    }
+    pub fn next_subject_id(&self) -> SubjectId {
+        self.subjects.iter().map(|s| s.id).max().unwrap_or(0) + 1
+    }
+    /// Subject the timetable of a class has in `slot` on `weekday`
+    pub fn planned_subject(
+        &self,
+        cohort: Option<CohortId>,
+        weekday: Weekday,
+        slot: usize,
+    ) -> Option<SubjectId> {
+        let at = WeekSlot { weekday, slot };
+        self.active_subjects(cohort)
+            .iter()
+            .find(|s| s.slots.contains(&at))
+            .map(|s| s.id)
+    }
+    /// First slot the timetable of a class has a lesson in on `weekday`
+    pub fn first_planned_slot(&self, cohort: Option<CohortId>, weekday: Weekday) -> Option<usize> {
+        self.active_subjects(cohort)
+            .iter()
+            .flat_map(|s| &s.slots)
+            .filter(|w| w.weekday == weekday)
+            .map(|w| w.slot)
+            .min()
+    }
+    pub fn subject_in_use(&self, id: SubjectId) -> bool {
+        self.data.iter().any(|e| e.lesson == id) || self.absences.iter().any(|a| a.lesson == id)
+    }
+
     // Vec<Subject, Number of lates, Sum of lates, Percentage
-    pub fn ranking_vec_lesson(&self, rank: Option<Ranking>) -> Vec<(Lesson, i32, u32, u32)> {
-        let mut tupples: Vec<(Lesson, i32, u32, u32)> = Lesson::all()
-            .into_iter()
-            .map(|n: Lesson| (n.clone(), 0, self.sum_lesson(n), 0))
+    // Archived subjects only show up if there is history for them
+    pub fn ranking_vec_lesson(&self, rank: Option<Ranking>) -> Vec<(Subject, i32, u32, u32)> {
+        let mut tupples: Vec<(Subject, i32, u32, u32)> = self
+            .subjects
+            .iter()
+            .filter(|n| n.active || self.data.iter().any(|e| e.lesson == n.id))
+            .map(|n: &Subject| (n.clone(), 0, self.sum_lesson(n.id), 0))
             .collect();
         for entry in &self.data {
             for t in &mut tupples {
-                if entry.lesson == t.0 {
+                if entry.lesson == t.0.id {
                     t.1 += 1;
                 }
             }
@@ -182,19 +881,36 @@ impl DataBase {
         tupples
     }
 
-    // Vec<Person, Number of lates, Sum of lates, Percentage
-    pub fn ranking_vec(&self, rank: Option<Ranking>) -> Vec<(Class, i32, u32, u32)> {
-        let mut tupples: Vec<(Class, i32, u32, u32)> = Class::all()
-            .into_iter()
-            .map(|n: Class| (n.clone(), 0, self.sum_person(n), 0))
-            .collect();
+    /// Number of lates and their minutes per student
+    pub fn lates_per_person(&self) -> Vec<Lates> {
+        let mut lates: Vec<Lates> = vec![];
         for entry in &self.data {
-            for t in &mut tupples {
-                if entry.person == t.0 {
-                    t.1 += 1;
+            match lates.iter_mut().find(|l| l.0 == entry.person) {
+                Some(l) => {
+                    l.1 += 1;
+                    l.2 += entry.delay_min;
                 }
+                None => lates.push((entry.person, 1, entry.delay_min)),
             }
         }
+        lates
+    }
+
+    // Vec<Person, Number of lates, Sum of lates, Percentage
+    // `lates` comes from `lates_per_person` or the storage backend
+    pub fn ranking_vec(
+        &self,
+        lates: &[Lates],
+        rank: Option<Ranking>,
+    ) -> Vec<(Student, i32, u32, u32)> {
+        let mut tupples: Vec<(Student, i32, u32, u32)> = self
+            .roster(None)
+            .into_iter()
+            .map(|n: Student| match lates.iter().find(|l| l.0 == n.id) {
+                Some(l) => (n, l.1 as i32, l.2, 0),
+                None => (n, 0, 0, 0),
+            })
+            .collect();
         for entry in &mut tupples {
             let percent = (entry.1 as f32 / self.data.len() as f32 * 100.0).round();
             entry.3 = percent as u32;
@@ -243,7 +959,7 @@ impl DataBase {
         let sum: u32 = self.data.iter().map(|x| x.delay_min).sum();
         return sum;
     }
-    fn sum_person(&self, person: Class) -> u32 {
+    fn sum_person(&self, person: StudentId) -> u32 {
         self.data
             .iter()
             .filter(|x| x.person == person)
@@ -251,7 +967,7 @@ impl DataBase {
             .sum()
     }
 
-    fn sum_lesson(&self, lesson: Lesson) -> u32 {
+    fn sum_lesson(&self, lesson: SubjectId) -> u32 {
         self.data
             .iter()
             .filter(|x| x.lesson == lesson)
@@ -259,17 +975,23 @@ impl DataBase {
             .sum()
     }
 
-    pub fn penalties_person(&self, person: Class) -> u32 {
-        self.entries_person_num(person) / 3
+    /// Every third unexcused late is a penalty
+    pub fn penalties_person(&self, person: StudentId) -> u32 {
+        let unexcused = self
+            .data
+            .iter()
+            .filter(|e| e.person == person && !e.excused)
+            .count() as u32;
+        unexcused / 3
     }
     pub fn total_penalties(&self) -> u32 {
-        Class::all()
-            .into_iter()
-            .map(|x| self.penalties_person(x))
+        self.students
+            .iter()
+            .map(|x| self.penalties_person(x.id))
             .sum()
     }
 
-    fn entries_person_num(&self, person: Class) -> u32 {
+    fn entries_person_num(&self, person: StudentId) -> u32 {
         let mut n = 0;
         for entry in &self.data {
             if entry.person == person {
@@ -302,11 +1024,11 @@ impl DataBase {
         }
         return tupples.into_iter().map(|n: Lesson, m, i| (n, m));
     }*/
-    pub fn get_profile_stats(&self, person: Class) -> ProfileStats {
-        let mut stats = ProfileStats::empty(person.clone());
-        stats.sum = self.sum_person(person.clone());
-        stats.num = self.entries_person_num(person.clone());
-        stats.theo_penalties = self.penalties_person(person.clone());
+    pub fn get_profile_stats(&self, person: StudentId) -> ProfileStats {
+        let mut stats = ProfileStats::empty(person);
+        stats.sum = self.sum_person(person);
+        stats.num = self.entries_person_num(person);
+        stats.theo_penalties = self.penalties_person(person);
         let mut num_first = 0;
         for entry in &self.data {
             if entry.person == person {
@@ -340,128 +1062,18 @@ impl DataBase {
     }
 }
 
-impl std::fmt::Display for Class {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub enum Class {
-    Nicole,
-    Sophia,
-    Emily,
-    Leander,
-    Suya,
-    Jan,
-    Liam,
-    Evelin,
-    Isabelle,
-    Anina,
-    Carlo,
-    Levin,
-    Anne,
-    Kilian,
-    Jonah,
-    Ida,
-    Neda,
-    Antonie,
-    Laurin,
-    Marija,
-    Raphael,
-    Elena,
-    Luis,
-    Mia,
-    Marie,
-}
-
-impl Class {
-    pub fn all() -> Vec<Class> {
-        let mut a = vec![
-            Class::Nicole,
-            Class::Emily,
-            Class::Leander,
-            Class::Suya,
-            Class::Jan,
-            Class::Liam,
-            Class::Evelin,
-            Class::Isabelle,
-            Class::Anina,
-            Class::Carlo,
-            Class::Levin,
-            Class::Anne,
-            Class::Kilian,
-            Class::Jonah,
-            Class::Ida,
-            Class::Neda,
-            Class::Antonie,
-            Class::Laurin,
-            Class::Marija,
-            Class::Raphael,
-            Class::Elena,
-            Class::Luis,
-            Class::Mia,
-            Class::Marie,
-            Class::Sophia,
-        ];
-        a.sort_by(|a, b| a.to_string().cmp(&b.to_string()));
-        a
-    }
-}
-
-impl std::fmt::Display for Lesson {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
-pub enum Lesson {
-    Mathe,
-    Bio,
-    Deutsch,
-    PFPhysik,
-    Physik,
-    Franzoesisch,
-    Italienisch,
-    Geschichte,
-    PFGeschichte,
-    Englisch,
-    PFEnglisch,
-    Chemie,
-    EFChemie,
-    Sport,
-    BG,
-    EFBG,
-    Musik,
-    Griechisch,
-    Geographie,
-    EFGeographie,
-    EFPhilosophie,
-    EFSport,
+pub fn prev_path(path: &str) -> String {
+    format!("{}.prev", path)
 }
 
-impl Lesson {
-    pub fn all() -> Vec<Lesson> {
-        vec![
-            Lesson::Mathe,
-            Lesson::Bio,
-            Lesson::Deutsch,
-            Lesson::PFPhysik,
-            Lesson::Franzoesisch,
-            Lesson::Italienisch,
-            Lesson::PFGeschichte,
-            Lesson::PFEnglisch,
-            Lesson::Englisch,
-            Lesson::EFChemie,
-            Lesson::Sport,
-            //Lesson::BG,
-            Self::EFBG,
-            //Lesson::Musik,
-            Lesson::Griechisch,
-            //Lesson::Geographie,
-            Lesson::EFGeographie,
-            Lesson::EFPhilosophie,
-            Lesson::EFSport,
-        ]
-    }
-}
+/// Colours handed out to subjects that don't have one yet
+pub const SUBJECT_COLORS: [[u8; 3]; 8] = [
+    [0x4c, 0x8b, 0xf5],
+    [0xe0, 0x6c, 0x75],
+    [0x98, 0xc3, 0x79],
+    [0xe5, 0xc0, 0x7b],
+    [0xc6, 0x78, 0xdd],
+    [0x56, 0xb6, 0xc2],
+    [0xd1, 0x9a, 0x66],
+    [0xab, 0xb2, 0xbf],
+];
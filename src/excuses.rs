@@ -0,0 +1,285 @@
+use crate::bootstrap::*;
+use crate::calendar::Calendar;
+use crate::db::{AbsenceId, AbsenceRecord, Change, ExcuseStatus, LessonAbs, StudentId};
+use crate::themes::{self, styled_button, ColorType};
+use crate::time;
+use crate::{App, Message};
+use chrono::NaiveDate;
+use iced::widget::{
+    button, column, horizontal_rule, horizontal_space, pick_list, row, scrollable, text,
+    text_input, toggler,
+};
+use iced::{Alignment, Element, Length, Task};
+
+/// Open excuses due within this many school days are shown as due soon
+const DUE_SOON: u32 = 3;
+
+/// Choices for the excuse deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExcuseDays(pub u32);
+
+impl ExcuseDays {
+    pub fn all() -> Vec<Self> {
+        [5, 10, 15, 20].into_iter().map(ExcuseDays).collect()
+    }
+}
+
+impl std::fmt::Display for ExcuseDays {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} Schultage", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Due {
+    Overdue,
+    Soon,
+    Later,
+}
+
+pub fn due(record: &AbsenceRecord, today: NaiveDate, calendar: &Calendar) -> Option<Due> {
+    if record.status != ExcuseStatus::Open {
+        return None;
+    }
+    if record.deadline < today {
+        Some(Due::Overdue)
+    } else if record.deadline <= time::add_school_days(today, DUE_SOON, calendar) {
+        Some(Due::Soon)
+    } else {
+        Some(Due::Later)
+    }
+}
+
+/// Open excuses of `absences` that are overdue and due soon
+pub fn due_counts<'a>(
+    absences: impl Iterator<Item = &'a LessonAbs>,
+    today: NaiveDate,
+    calendar: &Calendar,
+) -> (usize, usize) {
+    let dues: Vec<Due> = absences
+        .flat_map(|a| &a.records)
+        .filter_map(|r| due(r, today, calendar))
+        .collect();
+    (
+        dues.iter().filter(|d| **d == Due::Overdue).count(),
+        dues.iter().filter(|d| **d == Due::Soon).count(),
+    )
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExcusesState {
+    show_all: bool,
+    editing: Option<(AbsenceId, StudentId)>,
+    note: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExcusesMsg {
+    SetStatus(AbsenceId, StudentId, ExcuseStatus),
+    EditNote(AbsenceId, StudentId),
+    Note(String),
+    SaveNote,
+    Days(ExcuseDays),
+    ShowAll(bool),
+}
+
+/// Changes the record of `student` in absence `id` as one undoable step
+fn update_record(
+    app: &mut App,
+    id: AbsenceId,
+    student: StudentId,
+    f: impl FnOnce(&mut AbsenceRecord),
+) {
+    let Some(mut a) = app.db.absence(id).cloned() else {
+        return;
+    };
+    let Some(r) = a.records.iter_mut().find(|r| r.student == student) else {
+        return;
+    };
+    f(r);
+    app.commit(Change::UpdateAbsence(a));
+}
+
+pub fn update_excuses(app: &mut App, msg: ExcusesMsg) -> Task<Message> {
+    match msg {
+        ExcusesMsg::SetStatus(id, student, status) => {
+            update_record(app, id, student, |r| r.status = status)
+        }
+        ExcusesMsg::EditNote(id, student) => {
+            app.excuses.note = app
+                .db
+                .absence(id)
+                .and_then(|a| a.record(student))
+                .map(|r| r.note.clone())
+                .unwrap_or_default();
+            app.excuses.editing = Some((id, student));
+        }
+        ExcusesMsg::Note(s) => app.excuses.note = s,
+        ExcusesMsg::SaveNote => {
+            if let Some((id, student)) = app.excuses.editing.take() {
+                let note = std::mem::take(&mut app.excuses.note).trim().to_string();
+                update_record(app, id, student, |r| r.note = note);
+            }
+        }
+        ExcusesMsg::Days(d) => {
+            app.settings.excuse_days = d.0;
+            app.mark_changed();
+        }
+        ExcusesMsg::ShowAll(b) => app.excuses.show_all = b,
+    }
+    Task::none()
+}
+
+fn record_row<'a>(
+    app: &'a App,
+    a: &LessonAbs,
+    r: &AbsenceRecord,
+    today: NaiveDate,
+) -> Element<'a, Message> {
+    let due = due(r, today, &app.settings.calendar);
+    let deadline_style: fn(&iced::Theme) -> text::Style = match due {
+        Some(Due::Overdue) | Some(Due::Soon) => themes::text_fg_danger,
+        _ => themes::text_fg_sec,
+    };
+    let status_style: fn(&iced::Theme) -> text::Style = match r.status {
+        ExcuseStatus::Open => themes::text_fg,
+        ExcuseStatus::Excused => themes::text_fg_succes,
+        ExcuseStatus::Unexcused => themes::text_fg_danger,
+    };
+    let set = |status| Message::Excuses(ExcusesMsg::SetStatus(a.id, r.student, status));
+
+    let mut details = column![row![
+        text(app.db.student_name(r.student)).size(18),
+        text(format!(
+            "{}, {} {}",
+            app.db.subject_name(a.lesson),
+            a.date.format("%d.%m.%Y"),
+            a.lesson_time.format("%H:%M")
+        ))
+        .style(themes::text_fg_sec),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center)];
+    if app.excuses.editing == Some((a.id, r.student)) {
+        details = details.push(
+            text_input("Notiz", &app.excuses.note)
+                .on_input(|s| Message::Excuses(ExcusesMsg::Note(s)))
+                .on_submit(Message::Excuses(ExcusesMsg::SaveNote)),
+        );
+    } else if !r.note.is_empty() {
+        details = details.push(text(r.note.clone()).size(12).style(themes::text_fg_sec));
+    }
+
+    let mut line = row![
+        details.width(Length::Fill),
+        text(format!(
+            "{}{}",
+            match due {
+                Some(Due::Overdue) => "Überfällig seit ",
+                _ => "Frist ",
+            },
+            r.deadline.format("%d.%m.%Y")
+        ))
+        .style(deadline_style),
+        text(r.status.to_string()).style(status_style).width(100),
+        button(text(icon_to_string(Bootstrap::PencilFill)).font(ICON_FONT))
+            .on_press(Message::Excuses(ExcusesMsg::EditNote(a.id, r.student)))
+            .style(button::text),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    line = match r.status {
+        ExcuseStatus::Open => line
+            .push(styled_button(
+                Bootstrap::CheckCircleFill,
+                "Entschuldigt",
+                set(ExcuseStatus::Excused),
+                ColorType::Succes,
+            ))
+            .push(styled_button(
+                Bootstrap::XCircleFill,
+                "Unentschuldigt",
+                set(ExcuseStatus::Unexcused),
+                ColorType::Danger,
+            )),
+        _ => line.push(styled_button(
+            Bootstrap::ArrowCounterclockwise,
+            "Wieder öffnen",
+            set(ExcuseStatus::Open),
+            ColorType::Secondary,
+        )),
+    };
+    line.into()
+}
+
+pub fn excuses_view(app: &App) -> Element<Message> {
+    let today = time::get_today();
+    let mut records: Vec<(&LessonAbs, &AbsenceRecord)> = app
+        .db
+        .absences
+        .iter()
+        .filter(|a| Some(a.cohort) == app.cohort)
+        .flat_map(|a| a.records.iter().map(move |r| (a, r)))
+        .filter(|(_, r)| app.excuses.show_all || r.status == ExcuseStatus::Open)
+        .collect();
+    records.sort_by_key(|(a, r)| (r.deadline, a.date, a.lesson_time));
+
+    let mut list = column![].spacing(5);
+    for (a, r) in &records {
+        list = list.push(record_row(app, a, r, today));
+    }
+    if records.is_empty() {
+        list = list.push(text("Keine offenen Entschuldigungen").style(themes::text_fg_succes));
+    }
+
+    let (overdue, soon) = due_counts(
+        app.db
+            .absences
+            .iter()
+            .filter(|a| Some(a.cohort) == app.cohort),
+        today,
+        &app.settings.calendar,
+    );
+    let header = row![
+        button(
+            row![
+                text(icon_to_string(Bootstrap::ArrowLeftSquareFill))
+                    .font(ICON_FONT)
+                    .style(themes::text_fg)
+                    .size(22),
+                text("Zurück").style(themes::text_fg).size(20)
+            ]
+            .spacing(5)
+            .align_y(Alignment::Center)
+        )
+        .on_press(Message::BackView)
+        .style(button::text),
+        text("Entschuldigungen").size(22).style(themes::text_fg),
+        text(format!("{} überfällig, {} bald fällig", overdue, soon)).style(if overdue > 0 {
+            themes::text_fg_danger
+        } else {
+            themes::text_fg_sec
+        }),
+        horizontal_space(),
+        text("Frist:"),
+        pick_list(
+            ExcuseDays::all(),
+            Some(ExcuseDays(app.settings.excuse_days)),
+            |d| Message::Excuses(ExcusesMsg::Days(d))
+        ),
+        toggler(app.excuses.show_all)
+            .label("Erledigte zeigen")
+            .on_toggle(|b| Message::Excuses(ExcusesMsg::ShowAll(b))),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+
+    column![
+        header,
+        horizontal_rule(1),
+        scrollable(list).style(themes::scrollbar_invis),
+    ]
+    .spacing(5)
+    .padding(20)
+    .into()
+}
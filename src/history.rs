@@ -0,0 +1,71 @@
+use crate::db::Change;
+
+/// How many steps are kept, the oldest are dropped first
+const MAX_STEPS: usize = 100;
+
+/// A change that was made together with the change that takes it back
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub id: u64,
+    pub label: &'static str,
+    pub forward: Change,
+    pub backward: Change,
+}
+
+/// Undo and redo stacks for everything that went through `App::commit`
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    next_id: u64,
+}
+
+impl History {
+    /// Records a new change and returns the id of its step. Anything that
+    /// could have been redone is gone after this.
+    pub fn record(&mut self, forward: Change, backward: Change) -> u64 {
+        self.next_id += 1;
+        self.undo.push(Step {
+            id: self.next_id,
+            label: forward.label(),
+            forward,
+            backward,
+        });
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.next_id
+    }
+
+    pub fn undo(&mut self) -> Option<Step> {
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step)
+    }
+
+    pub fn redo(&mut self) -> Option<Step> {
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+
+    /// Id of the step the next undo would take back
+    pub fn last_id(&self) -> Option<u64> {
+        self.undo.last().map(|s| s.id)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets everything, used when a different database was loaded
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
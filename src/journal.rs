@@ -0,0 +1,60 @@
+use crate::crypt;
+use crate::db::{Change, DataBaseError};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+/// Append-only log of the changes made since the last save, one JSON line
+/// per change. After a crash it is replayed over the last saved database.
+#[derive(Debug, Clone)]
+pub struct Journal {
+    path: String,
+}
+
+impl Journal {
+    /// The journal lives next to the database it belongs to
+    pub fn for_storage(path: &str) -> Self {
+        Journal {
+            path: format!("{}.journal", path),
+        }
+    }
+
+    pub fn append(&self, change: &Change) -> Result<(), DataBaseError> {
+        let line = crypt::seal(&serde_json::to_string(change)?)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| DataBaseError::from(e).at(&self.path))?;
+        writeln!(file, "{}", line)
+            .and_then(|_| file.sync_data())
+            .map_err(|e| DataBaseError::from(e).at(&self.path))
+    }
+
+    /// The recorded changes in order. A line cut short by the crash ends
+    /// the journal, everything before it is still used.
+    pub fn read(&self) -> Result<Vec<Change>, DataBaseError> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(DataBaseError::from(e).at(&self.path)),
+        };
+        let mut changes = vec![];
+        for line in content.lines() {
+            match serde_json::from_str(&crypt::open(line)?) {
+                Ok(c) => changes.push(c),
+                Err(_) => break,
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Called once everything in the journal is part of a saved database
+    pub fn clear(&self) -> Result<(), DataBaseError> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(DataBaseError::from(e).at(&self.path))
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use crate::bootstrap::*;
-use crate::db::Class;
+use crate::db::{DataBase, Entry, SubjectId};
 use crate::themes::{self, text_fg, text_fg_succes};
-use crate::{App, Message, ViewControl};
+use crate::{absences, App, Message, ViewControl};
 use iced::widget::{
     button, checkbox, column, combo_box, container, horizontal_rule, horizontal_space, pick_list,
     row, scrollable, slider, stack, text, text_input, toggler, tooltip, vertical_rule,
@@ -56,18 +56,50 @@ impl Default for ListState {
     }
 }
 
+/// Subject name in the colour of the catalogue
+pub fn subject_text<'a>(db: &DataBase, id: SubjectId) -> Element<'a, Message> {
+    match db.subject(id) {
+        Some(s) => text(s.name.clone()).color(s.iced_color()).into(),
+        None => text(db.subject_name(id))
+            .style(|theme: &Theme| text::primary(theme))
+            .into(),
+    }
+}
+
+/// Reason of a late with the excused mark and the note below it
+pub fn reason_text<'a>(entry: &Entry) -> Element<'a, Message> {
+    let style = match entry.excused {
+        true => text_fg_succes,
+        false => text_fg,
+    };
+    let mut col = column![text(entry.reason.to_string()).style(style)];
+    if entry.excused {
+        col = col.push(text("entschuldigt").size(12).style(text_fg_succes));
+    }
+    if !entry.note.is_empty() {
+        col = col.push(text(entry.note.clone()).size(12).style(themes::text_fg_sec));
+    }
+    col.width(150).into()
+}
+
 pub fn delays_list(app: &App) -> Element<Message> {let mut entries = column![];
-    for entry in &app.db.data {
-        let t = text(entry.person.to_string());
+    for entry in app
+        .db
+        .data
+        .iter()
+        .filter(|e| app.db.in_cohort(e.person, app.cohort))
+    {
+        let t = text(app.db.student_name(entry.person));
         entries = entries.push(column![
             row![
                 t.size(20).style(text::success),
                 horizontal_space(),
-                text(entry.lesson.to_string()).style(|theme: &Theme| text::primary(theme)),
+                subject_text(&app.db, entry.lesson),
                 text(format!(" Erste Lektion: {} ", entry.first_lesson)),
                 text(format!("{} Min", entry.delay_min))
                     .style(|theme: &Theme| text::primary(theme)),
                 horizontal_space(),
+                reason_text(entry),
                 column![
                     text(entry.lesson_time.to_string()),
                     text(entry.date.to_string())
@@ -80,9 +112,16 @@ pub fn delays_list(app: &App) -> Element<Message> {let mut entries = column![];
                             .size(22)
                             .style(themes::text_fg_danger)
                     )
-                    .on_press(Message::DLEntry(entry.clone()))
+                    .on_press(Message::DLEntry(entry.id))
+                    .style(button::text),
+                    button(
+                        text(icon_to_string(Bootstrap::PencilFill))
+                            .font(ICON_FONT)
+                            .size(22)
+                            .style(themes::text_fg)
+                    )
+                    .on_press(Message::Edit(entry.id))
                     .style(button::text),
-                    //button("Bearbeiten").on_press(Message::Edit)
                 ]
                 .align_x(Alignment::Center)
                 .spacing(5)
@@ -98,15 +137,20 @@ pub fn delays_list(app: &App) -> Element<Message> {let mut entries = column![];
 }
 pub fn absences_list(app: &App) -> Element<Message> {
     let mut entries = column![];
-    for entry in &app.db.absences {
-        let t = text(entry.lesson.to_string());
+    for entry in app
+        .db
+        .absences
+        .iter()
+        .filter(|a| Some(a.cohort) == app.cohort)
+    {
+        let t = text(app.db.subject_name(entry.lesson));
         entries = entries.push(column![
             row![
                 t.size(20).style(text::success),
                 horizontal_space(),
-                text(entry.lesson.to_string()).style(|theme: &Theme| text::primary(theme)),
+                subject_text(&app.db, entry.lesson),
                 text(format!(" Erste Lektion: {} ", entry.first_lesson)),
-                text(format!("{} Absenzen", crate::db::Class::all().len() - entry.present.len()))
+                text(format!("{} Absenzen", entry.absent_count()))
                     .style(|theme: &Theme| text::primary(theme)),
                 horizontal_space(),
                 column![
@@ -114,26 +158,16 @@ pub fn absences_list(app: &App) -> Element<Message> {
                     text(entry.date.to_string())
                 ]
                 .padding(3),
-                column![
-                    button(
-                        text(icon_to_string(Bootstrap::TrashthreeFill))
-                            .font(ICON_FONT)
-                            .size(22)
-                            .style(themes::text_fg_danger)
-                    )
-                    .on_press(Message::Nothing)
-                    .style(button::text),
-                    //button("Bearbeiten").on_press(Message::Edit)
-                ]
-                .align_x(Alignment::Center)
-                .spacing(5)
-                .padding(5),
+                absences::absence_actions(app, entry.id),
             ]
             .spacing(5)
             .padding(20)
             .align_y(Alignment::Center),
-            horizontal_rule(1),
         ]);
+        if app.absences.detail == Some(entry.id) {
+            entries = entries.push(absences::absence_detail(app, entry));
+        }
+        entries = entries.push(horizontal_rule(1));
     }
     entries.into()
 }
@@ -1,55 +1,118 @@
 #![windows_subsystem = "windows"]
 
-use chrono::{Datelike, Local};
+use chrono::Datelike;
 use iced::event::{self, Event};
 use iced::widget::{
     button, checkbox, column, combo_box, container, horizontal_rule, horizontal_space, pick_list,
     row, scrollable, slider, stack, text, text_input, toggler, tooltip, vertical_rule,
     vertical_space,
 };
+use iced::keyboard::{self, Key};
 use iced::window;
 use iced::{alignment, Alignment, Element, Font, Length, Padding, Subscription, Task, Theme};
 
 pub mod bootstrap;
 use bootstrap::*;
 pub mod absences;
+pub mod backup;
+pub mod calendar;
+pub mod check;
+pub mod crypt;
 pub mod toast;
 use toast::*;
 pub mod db;
+pub mod excuses;
+pub mod history;
+pub mod journal;
 pub mod list;
 pub mod menu;
+pub mod merge;
+pub mod migrate;
 pub mod new;
+pub mod paths;
+pub mod retention;
+pub mod roster;
+pub mod schedule;
 pub mod settings;
+pub mod spans;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod sqlite;
 pub mod stats;
+pub mod storage;
+pub mod subjects;
 pub mod themes;
 pub mod time;
+pub mod timetable;
+pub mod unlock;
 
-use db::{Class, DataBase, DataBaseError, Lesson};
+use db::{Cohort, CohortId, DataBase, DataBaseError, Student, Subject};
 use stats::{update_stats, StatState, StatsMessage};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use storage::{Storage, StorageKind};
 
 //#[cfg(not(target_arch = "wasm32"))]
 //#[tokio::main]
 //async fn main() -> iced::Result {
 fn main() -> iced::Result {
+    let mut args: Vec<String> = std::env::args().collect();
+    let mut data_dir = None;
+    if let Some(i) = args.iter().position(|a| a.starts_with("--data-dir")) {
+        let arg = args.remove(i);
+        match arg.strip_prefix("--data-dir=") {
+            Some(dir) => data_dir = Some(std::path::PathBuf::from(dir)),
+            None if arg == "--data-dir" && i < args.len() => {
+                data_dir = Some(std::path::PathBuf::from(args.remove(i)))
+            }
+            None => (),
+        }
+    }
+    match paths::init(data_dir) {
+        Ok(moved) if !moved.is_empty() => report(
+            false,
+            &format!(
+                "Nach {} verschoben: {}",
+                paths::data_dir().display(),
+                moved.join(", ")
+            ),
+        ),
+        Ok(_) => (),
+        Err(e) => report(
+            true,
+            &format!(
+                "Datenordner {} nicht verwendbar: {}",
+                paths::data_dir().display(),
+                e
+            ),
+        ),
+    }
+
+    if args.len() == 4 && args[1] == "--convert" {
+        match storage::convert(&args[2], &args[3]) {
+            Ok(db) => report(
+                false,
+                &format!(
+                    "{} Verspätungen und {} Absenzen von {} nach {} übertragen",
+                    db.data.len(),
+                    db.absences.len(),
+                    args[2],
+                    args[3]
+                ),
+            ),
+            Err(e) => report(true, &format!("Konvertierung fehlgeschlagen: {}", e)),
+        }
+        return Ok(());
+    }
+
     iced::application(
         || {
             (
                 App::new().0,
+                // The settings decide which storage the database is loaded from
                 Task::perform(
-                    db::DataBase::load_file("db.json"),
-                    |r: Result<DataBase, DataBaseError>| match r {
-                        Ok(db) => Message::DBLoaded(db),
-                        Err(_) => Message::Notify(Toast::new(
-                            "Error",
-                            "DB nix geladen oopsiwoopsy",
-                            Status::Danger,
-                        )),
-                    },
-                )
-                .chain(Task::perform(
-                    settings::load_from_file("settings.json"),
+                    settings::load_from_file(paths::data_file("settings.json")),
                     Message::SettingsLoaded,
-                )),
+                ),
             )
         },
         App::update,
@@ -63,11 +126,54 @@ fn main() -> iced::Result {
     .run()
 }
 
+/// Messages from before the window opens. Windows builds have no console to
+/// print to, so they are shown in a dialog there as well.
+fn report(error: bool, message: &str) {
+    if error {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+    #[cfg(windows)]
+    rfd::MessageDialog::new()
+        .set_title("Verspätungsmanager4002 Ultra Pro Max")
+        .set_description(message)
+        .set_level(if error {
+            rfd::MessageLevel::Error
+        } else {
+            rfd::MessageLevel::Info
+        })
+        .show();
+}
+
 const ICON_FONT: Font = Font::with_name("bootstrap-icons");
 
-async fn save_all(db: db::DataBase, sets: settings::Settings) -> Result<(), db::DataBaseError> {
-    settings::save_to_file(sets, "settings.json").await;
-    return db.save_file("db.json".to_string()).await;
+/// `db` is `None` while the database on disk must not be overwritten
+async fn save_all(
+    storage: Arc<dyn Storage>,
+    db: Option<db::DataBase>,
+    sets: settings::Settings,
+) -> Result<(), db::DataBaseError> {
+    settings::save_to_file(sets, &paths::data_file("settings.json")).await;
+    match db {
+        Some(db) => storage.save(&db),
+        None => Ok(()),
+    }
+}
+
+fn db_loaded(r: Result<(DataBase, bool), DataBaseError>) -> Message {
+    match r {
+        Ok((db, false)) => Message::DBLoaded(db),
+        Ok((db, true)) => Message::DBRecovered(db),
+        // First start, the file is created on the next save
+        Err(e) if e.is_not_found() => Message::DBLoaded(DataBase::empty()),
+        Err(DataBaseError::Locked) => Message::Unlock(unlock::UnlockMsg::Required(None)),
+        Err(e @ DataBaseError::WrongPassphrase) => {
+            Message::Unlock(unlock::UnlockMsg::Required(Some(e.to_string())))
+        }
+        // Anything else could be fixed by hand, so never save over it
+        Err(e) => Message::DBRefused(e.to_string()),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -78,18 +184,29 @@ pub enum ViewControl {
     SETTINGS,
     MENU,
     ABSENCES,
+    CHECK,
+    UNLOCK,
+    EXCUSES,
 }
 
 pub struct App {
     add_entry: db::Entry,
+    /// Id of the entry the form is currently editing, `None` for a new entry
+    editing: Option<db::EntryId>,
     db: db::DataBase,
+    storage: Arc<dyn Storage>,
+    settings: settings::Settings,
+    /// Class everything but the "all classes" statistics is scoped to
+    cohort: Option<CohortId>,
+    /// Set if db.json could not be loaded safely, it is then never overwritten
+    db_locked: bool,
     view: ViewControl,
     view_origin: ViewControl,
-    combo: combo_box::State<Class>,
-    sel_pers: Option<Class>,
+    combo: combo_box::State<Student>,
+    sel_pers: Option<Student>,
 
-    combo2: combo_box::State<Lesson>,
-    sel_lesson: Option<Lesson>,
+    combo2: combo_box::State<Subject>,
+    sel_lesson: Option<Subject>,
     status_text: String,
     theme_state: combo_box::State<Theme>,
     selected_theme: Option<Theme>,
@@ -103,6 +220,37 @@ pub struct App {
     menu: menu::MenuState,
 
     list: list::ListState,
+
+    roster: roster::RosterState,
+
+    subjects: subjects::SubjectsState,
+
+    history: history::History,
+
+    backup: backup::BackupState,
+
+    merge: merge::MergeState,
+
+    unlock: unlock::UnlockState,
+
+    retention: retention::RetentionState,
+
+    excuses: excuses::ExcusesState,
+
+    span: spans::SpanState,
+
+    absences: absences::AbsState,
+
+    schedule: schedule::ScheduleState,
+    calendar: calendar::CalendarState,
+
+    journal: journal::Journal,
+    /// Counts the changes, the database is unsaved while it is ahead of `saved_revision`
+    revision: u64,
+    saved_revision: u64,
+    last_change: Instant,
+    dirty_since: Option<Instant>,
+    saving: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -110,55 +258,110 @@ pub enum Message {
     Exit(Result<(), db::DataBaseError>),
     EventOccurred(Event),
     DBLoaded(DataBase),
+    DBRecovered(DataBase),
+    DBRefused(String),
+    DBError(String, db::DataBaseError),
     GoView(ViewControl),
+    SelectCohort(Cohort),
     BackView,
     Add,
     AddEntry,
-    SelectPerson(Class),
-    SelectLesson(Lesson),
+    SelectPerson(Student),
+    SelectLesson(Subject),
     IsFirst(bool),
     DelayE(u32),
+    EntryReason(db::Reason),
+    EntryNote(String),
+    Excused(bool),
     RemDay,
     AddDay,
-    DLEntry(db::Entry),
+    DLEntry(db::EntryId),
     LastLessonTime,
     NextLessonTime,
-    Edit,
+    Edit(db::EntryId),
     ThemeSelected(Theme),
     SettingsLoaded(settings::Settings),
+    SetStorage(StorageKind),
     Stats(StatsMessage),
     SaveExit,
-    BackupDB,
     DeleteDB,
     SaveDB,
     Notify(Toast),
     Nothing,
     CloseToast(usize),
+    ToastAction(usize, u64),
+    Undo,
+    Redo,
     Abs(absences::AbsMsg),
     MainMenu(menu::MenuMsg),
     List(list::ListMsg),
+    Roster(roster::RosterMsg),
+    Subjects(subjects::SubjectsMsg),
+    Timetable(timetable::TimetableMsg),
+    Backup(backup::BackupMsg),
+    Merge(merge::MergeMsg),
+    Check(check::CheckMsg),
+    Unlock(unlock::UnlockMsg),
+    Retention(retention::RetentionMsg),
+    Excuses(excuses::ExcusesMsg),
+    Span(spans::SpanMsg),
+    Schedule(schedule::ScheduleMsg),
+    Calendar(calendar::CalendarMsg),
+    AutosaveTick,
+    AutosaveSelected(settings::AutosaveDelay),
+    Saved {
+        revision: u64,
+        manual: bool,
+        result: Result<(), db::DataBaseError>,
+    },
 }
 
 impl App {
     fn new() -> (Self, Task<Message>) {
+        let schedules = schedule::Schedules::default();
+        let now = time::current_lesson(&schedules, &calendar::Calendar::default());
+        let start = now.start(&schedules);
         (
             Self {
-                add_entry: db::Entry::empty(),
+                add_entry: db::Entry::empty(now.date, start),
+                editing: None,
                 db: db::DataBase::empty(),
+                storage: Arc::new(storage::JsonStorage::new(&paths::data_file("db.json"))),
+                settings: settings::Settings::new(),
+                cohort: None,
+                db_locked: false,
                 view: ViewControl::MENU,
                 view_origin: ViewControl::MENU,
-                combo: combo_box::State::new(db::Class::all()),
+                combo: combo_box::State::new(vec![]),
                 sel_pers: None,
-                combo2: combo_box::State::new(db::Lesson::all()),
+                combo2: combo_box::State::new(vec![]),
                 sel_lesson: None,
                 status_text: String::new(),
                 theme_state: combo_box::State::new(Theme::ALL.to_vec()),
                 selected_theme: None,
                 stats: StatState::new(),
                 toasts: vec![],
-                abs: db::LessonAbs::new(),
+                abs: db::LessonAbs::new(now.date, start),
                 menu: menu::MenuState::new(),
                 list: list::ListState::default(),
+                roster: roster::RosterState::default(),
+                subjects: subjects::SubjectsState::default(),
+                history: history::History::default(),
+                backup: backup::BackupState::default(),
+                merge: merge::MergeState::default(),
+                unlock: unlock::UnlockState::default(),
+                retention: retention::RetentionState::default(),
+                excuses: excuses::ExcusesState::default(),
+                span: spans::SpanState::default(),
+                absences: absences::AbsState::default(),
+                schedule: schedule::ScheduleState::default(),
+                calendar: calendar::CalendarState::default(),
+                journal: journal::Journal::for_storage(&paths::data_file("db.json")),
+                revision: 0,
+                saved_revision: 0,
+                last_change: Instant::now(),
+                dirty_since: None,
+                saving: false,
             },
             Task::none(),
         )
@@ -167,68 +370,197 @@ impl App {
         String::from("Verspätungsmanager4002 Ultra Pro Max")
     }
     fn subscription(&self) -> Subscription<Message> {
-        event::listen().map(Message::EventOccurred)
+        let events = event::listen().map(Message::EventOccurred);
+        if self.is_dirty() && self.settings.autosave_secs > 0 {
+            let tick = iced::time::every(Duration::from_secs(1)).map(|_| Message::AutosaveTick);
+            Subscription::batch([events, tick])
+        } else {
+            events
+        }
     }
     fn update(&mut self, message: Message) -> Task<Message> {
         match message {
             Message::Exit(res) => match res {
                 Ok(_) => {
-                    println!("EXITING???");
+                    if !self.db_locked {
+                        let _ = self.journal.clear();
+                    }
                     return window::get_latest().and_then(window::close);
                 }
-                Err(_) => self.status_text = "Couldn't save data, not exiting".to_string(),
+                Err(e) => {
+                    return self.update(Message::DBError(
+                        "Nicht beendet, Speichern fehlgeschlagen".to_string(),
+                        e,
+                    ))
+                }
             },
             Message::SaveExit => {
-                let mut sets = settings::Settings::new();
-                match self.selected_theme.clone() {
-                    Some(theme) => {
-                        sets.theme = theme.to_string();
-                    }
-                    None => (),
-                }
-                return Task::perform(save_all(self.db.clone(), sets), Message::Exit);
+                let sets = self.current_settings();
+                return Task::perform(
+                    save_all(self.storage.clone(), self.db_for_saving(), sets),
+                    Message::Exit,
+                );
             }
             Message::EventOccurred(event) => {
                 if let Event::Window(window::Event::CloseRequested) = event {
-                    let mut sets = settings::Settings::new();
-                    match self.selected_theme.clone() {
-                        Some(theme) => {
-                            sets.theme = theme.to_string();
-                        }
-                        None => (),
-                    }
+                    let sets = self.current_settings();
                     /*return Task::perform(
                         self.db.clone().save_file("db.json".to_string()),
                         Message::Exit,
                     );*/
-                    return Task::perform(save_all(self.db.clone(), sets), Message::Exit);
+                    return Task::perform(
+                        save_all(self.storage.clone(), self.db_for_saving(), sets),
+                        Message::Exit,
+                    );
+                }
+                if let Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = event
+                {
+                    if let Key::Character(c) = key.as_ref() {
+                        // Without anything to take back the shortcuts do nothing
+                        let step = match c.to_lowercase().as_str() {
+                            "z" if modifiers.command() && modifiers.shift() => {
+                                self.history.can_redo().then_some(Message::Redo)
+                            }
+                            "z" if modifiers.command() => {
+                                self.history.can_undo().then_some(Message::Undo)
+                            }
+                            "y" if modifiers.command() => {
+                                self.history.can_redo().then_some(Message::Redo)
+                            }
+                            _ => None,
+                        };
+                        if let Some(step) = step {
+                            return Task::done(step);
+                        }
+                    }
                 }
                 return Task::none();
             }
             Message::DBLoaded(d) => {
                 self.db = d;
-                self.abs = db::LessonAbs::new_smart(&self.db.absences);
+                self.db_locked = false;
+                if let ViewControl::UNLOCK = self.view {
+                    self.view = ViewControl::MENU;
+                }
+                self.history.clear();
+                let mut tasks = vec![];
+                let mut replayed = false;
+                match self.journal.read() {
+                    Ok(changes) if !changes.is_empty() => {
+                        let n = changes.len();
+                        for c in changes {
+                            self.db.apply(c);
+                        }
+                        self.notify(Toast::new(
+                            "Wiederhergestellt",
+                            &format!("{} ungespeicherte Änderungen aus dem Journal übernommen", n),
+                            Status::Danger,
+                        ));
+                        self.mark_changed();
+                        replayed = true;
+                    }
+                    Ok(_) => (),
+                    Err(e) => tasks.push(self.update(Message::DBError(
+                        "Journal nicht gelesen".to_string(),
+                        e,
+                    ))),
+                }
+                // Purging saves as well, so the journal is only saved on its own
+                // if nothing was purged
+                match retention::apply_on_load(self) {
+                    Some(task) => tasks.push(task),
+                    None if replayed => tasks.push(self.save(false)),
+                    None => (),
+                }
+                self.cohort = self.db.cohorts.first().map(|c| c.id);
+                self.abs = self.fresh_absence();
+                self.refresh_pickers();
+                let settings = &self.settings;
+                let findings = check::check(&self.db, &settings.schedules, &settings.calendar)
+                    .iter()
+                    .filter(|f| f.severity >= check::Severity::Warning)
+                    .count();
+                if findings > 0 {
+                    self.notify(Toast::new(
+                        "Datenprüfung",
+                        &format!("{} Auffälligkeiten, siehe Einstellungen", findings),
+                        Status::Danger,
+                    ));
+                }
+                let (overdue, _) = excuses::due_counts(
+                    self.db.absences.iter(),
+                    time::get_today(),
+                    &self.settings.calendar,
+                );
+                if overdue > 0 {
+                    self.notify(Toast::new(
+                        "Entschuldigungen",
+                        &format!("{} Entschuldigungen sind überfällig", overdue),
+                        Status::Danger,
+                    ));
+                }
+                tasks.push(Task::perform(backup::auto_backup(self.db.clone()), |r| {
+                    Message::Backup(backup::BackupMsg::AutoCreated(r))
+                }));
+                return Task::batch(tasks);
+            }
+            Message::DBRecovered(d) => {
+                self.notify(Toast::new(
+                    "Warnung",
+                    "db.json war beschädigt, die letzte gute Kopie (db.json.prev) wurde geladen",
+                    Status::Danger,
+                ));
+                return self.update(Message::DBLoaded(d));
+            }
+            Message::DBRefused(msg) => {
+                self.db_locked = true;
+                self.status_text = msg.clone();
+                self.notify(Toast::new("DB nicht geladen", &msg, Status::Danger));
+            }
+            Message::SelectCohort(c) => {
+                self.cohort = Some(c.id);
+                let now = self.current_lesson();
+                self.add_entry = db::Entry::empty(now.date, now.start(&self.settings.schedules));
+                self.abs = self.fresh_absence();
+                self.refresh_pickers();
+                stats::refresh(self);
             }
             Message::GoView(v) => {
                 self.view_origin = self.view.clone();
                 self.view = v;
+                stats::refresh(self);
+                if let ViewControl::SETTINGS = self.view {
+                    return Task::done(Message::Backup(backup::BackupMsg::Refresh));
+                }
             }
             Message::BackView => {
                 self.view = self.view_origin.clone();
+                stats::refresh(self);
             }
             Message::Add => {
-                self.add_entry.lesson_time = time::get_last_lesson();
-                self.add_entry.date = time::get_today();
+                self.editing = None;
+                // Before the first lesson this is still yesterday's last one
+                let now = self.current_lesson();
+                self.add_entry = db::Entry::empty(now.date, now.start(&self.settings.schedules));
+                self.sel_pers = None;
+                self.sel_lesson = None;
+                if let Some(plan) = timetable::planned(&self.db, self.cohort, now) {
+                    if let Some(subject) = plan.subject.and_then(|id| self.db.subject(id)) {
+                        self.add_entry.lesson = subject.id;
+                        self.sel_lesson = Some(subject.clone());
+                    }
+                    self.add_entry.first_lesson = plan.first_lesson;
+                }
+                self.view_origin = self.view.clone();
                 self.view = ViewControl::ADD;
             }
             Message::SelectPerson(p) => {
-                self.add_entry.person = p.clone();
+                self.add_entry.person = p.id;
                 self.sel_pers = Some(p);
             }
             Message::SelectLesson(l) => {
-                self.add_entry.lesson = l.clone();
+                self.add_entry.lesson = l.id;
                 self.sel_lesson = Some(l);
-                println!("{}", self.add_entry.lesson_time);
             }
             Message::IsFirst(_) => {
                 self.add_entry.first_lesson = !self.add_entry.first_lesson;
@@ -236,94 +568,239 @@ impl App {
             Message::DelayE(d) => {
                 self.add_entry.delay_min = d;
             }
+            Message::EntryReason(r) => self.add_entry.reason = r,
+            Message::EntryNote(n) => self.add_entry.note = n,
+            Message::Excused(b) => self.add_entry.excused = b,
             Message::AddEntry => {
-                self.db.data.push(self.add_entry.clone());
-                self.view = ViewControl::LISTVIEW;
+                if self.sel_pers.is_none() || self.sel_lesson.is_none() {
+                    self.notify(Toast::new(
+                        "Fehler",
+                        "Person und Fach auswählen",
+                        Status::Danger,
+                    ));
+                    return Task::none();
+                }
+                let mut entry = self.add_entry.clone();
+                let change = match self.editing {
+                    Some(_) => db::Change::UpdateEntry(entry),
+                    None => {
+                        entry.id = self.db.next_entry_id();
+                        db::Change::AddEntry(entry)
+                    }
+                };
+                self.commit(change);
+                // Reason and note belong to the one late, not to the next
+                self.add_entry.reason = db::Reason::default();
+                self.add_entry.note.clear();
+                self.add_entry.excused = false;
+                if self.editing.take().is_some() {
+                    self.add_entry.id = 0;
+                    self.view = self.view_origin.clone();
+                    stats::refresh(self);
+                } else {
+                    self.view = ViewControl::LISTVIEW;
+                }
             }
             Message::AddDay => {
-                self.add_entry.date = self.add_entry.date.succ_opt().expect("Theres no tommorow?");
+                self.add_entry.date = self.settings.calendar.next_school_day(self.add_entry.date);
+                self.set_entry_slot(self.entry_slot());
             }
             Message::RemDay => {
-                self.add_entry.date = self
-                    .add_entry
-                    .date
-                    .pred_opt()
-                    .expect("There was no yesterday?");
-            }
-            Message::DLEntry(e) => {
-                for i in 0..self.db.data.len() {
-                    if self.db.data[i] == e {
-                        self.db.data.remove(i);
-                        break;
-                    }
-                }
+                self.add_entry.date = self.settings.calendar.prev_school_day(self.add_entry.date);
+                self.set_entry_slot(self.entry_slot());
+            }
+            Message::DLEntry(id) => {
+                let name = self
+                    .db
+                    .entry(id)
+                    .map(|e| self.db.student_name(e.person))
+                    .unwrap_or_default();
+                self.commit_undoable(
+                    db::Change::DeleteEntry(id),
+                    "Verspätung gelöscht",
+                    &name,
+                );
             }
             Message::LastLessonTime => {
-                self.add_entry.lesson_time = time::get_prev_lesson(self.add_entry.lesson_time);
+                let (settings, slot) = (&self.settings, self.entry_slot());
+                let slot = time::prev_lesson(&settings.schedules, &settings.calendar, slot);
+                self.set_entry_slot(slot);
             }
             Message::NextLessonTime => {
-                self.add_entry.lesson_time = time::get_next_lesson(self.add_entry.lesson_time);
+                let (settings, slot) = (&self.settings, self.entry_slot());
+                let slot = time::next_lesson(&settings.schedules, &settings.calendar, slot);
+                self.set_entry_slot(slot);
+            }
+            Message::Edit(id) => {
+                if let Some(entry) = self.db.entry(id) {
+                    self.add_entry = entry.clone();
+                    self.sel_pers = self.db.student(entry.person).cloned();
+                    self.sel_lesson = self.db.subject(entry.lesson).cloned();
+                    self.editing = Some(id);
+                    self.view_origin = self.view.clone();
+                    self.view = ViewControl::ADD;
+                }
+            }
+            Message::ThemeSelected(t) => {
+                self.selected_theme = Some(t);
+                self.mark_changed();
             }
-            Message::Edit => {}
-            Message::ThemeSelected(t) => self.selected_theme = Some(t),
             Message::SettingsLoaded(sets) => {
                 self.selected_theme = settings::string_to_theme(&sets.theme);
+                let opened = storage::open(sets.storage, &paths::data_file(sets.storage.file_name()));
+                self.settings = sets;
+                schedule::load_draft(self);
+                match opened {
+                    Ok(s) => {
+                        self.journal = journal::Journal::for_storage(s.path());
+                        self.storage = s;
+                    }
+                    Err(e) => return self.update(Message::DBRefused(e.to_string())),
+                }
+                let storage = self.storage.clone();
+                return Task::perform(async move { storage.load() }, db_loaded);
+            }
+            Message::SetStorage(kind) => {
+                if kind == self.settings.storage || self.db_locked {
+                    return Task::none();
+                }
+                if kind == StorageKind::Sqlite && crypt::active() {
+                    self.notify(Toast::new(
+                        "Nicht gewechselt",
+                        "SQLite kann nicht verschlüsselt werden, zuerst entschlüsseln",
+                        Status::Danger,
+                    ));
+                    return Task::none();
+                }
+                // Carry the current data over into the new format
+                let res = storage::open(kind, &paths::data_file(kind.file_name())).and_then(|s| {
+                    s.save(&self.db)?;
+                    Ok(s)
+                });
+                match res {
+                    Ok(s) => {
+                        // Everything is in the new file now
+                        let _ = self.journal.clear();
+                        self.journal = journal::Journal::for_storage(s.path());
+                        self.mark_saved();
+                        self.storage = s;
+                        self.settings.storage = kind;
+                        self.notify(Toast::new(
+                            "Speicherformat",
+                            &format!("Daten werden jetzt in {} gespeichert", kind.file_name()),
+                            Status::Success,
+                        ));
+                    }
+                    Err(e) => {
+                        return self.update(Message::DBError(
+                            "Speicherformat nicht gewechselt".to_string(),
+                            e,
+                        ))
+                    }
+                }
             }
             Message::Stats(t) => return update_stats(self, t),
-            Message::BackupDB => {
-                let name = Local::now().to_string();
-                return Task::perform(
-                    self.db
-                        .clone()
-                        .save_file(format!("{}-bak.json", name).to_string()),
-                    |r| -> Message {
-                        match r {
-                            Ok(_) => Message::Notify(Toast::new(
-                                "Success",
-                                "Created Backup",
-                                Status::Success,
-                            )),
-                            Err(_) => Message::Notify(Toast::new(
-                                "Error",
-                                "DatabaseError thrown",
-                                Status::Danger,
-                            )),
-                        }
-                    },
+            Message::DeleteDB => {
+                // Classes, roster and subjects are not part of the recorded lates, keep them
+                let mut db = self.db.clone();
+                db.data.clear();
+                db.absences.clear();
+                self.commit_undoable(
+                    db::Change::Replace(Box::new(db)),
+                    "Datenbank geleert",
+                    "Alle Verspätungen und Absenzen wurden gelöscht",
                 );
             }
-            Message::DeleteDB => {
-                self.db = DataBase::empty();
+            Message::SaveDB => return self.save(true),
+            Message::AutosaveTick => {
+                let delay = Duration::from_secs(self.settings.autosave_secs);
+                // Save once it has been quiet for a while, but don't wait
+                // forever while changes keep coming in
+                let idle = self.last_change.elapsed() >= delay;
+                let overdue = self.dirty_since.is_some_and(|t| t.elapsed() >= delay * 4);
+                if self.is_dirty() && !self.saving && !delay.is_zero() && (idle || overdue) {
+                    return self.save(false);
+                }
             }
-            Message::SaveDB => {
-                let mut sets = settings::Settings::new();
-                match self.selected_theme.clone() {
-                    Some(theme) => {
-                        sets.theme = theme.to_string();
+            Message::AutosaveSelected(d) => {
+                self.settings.autosave_secs = d.0;
+                self.mark_changed();
+            }
+            Message::Saved {
+                revision,
+                manual,
+                result,
+            } => {
+                self.saving = false;
+                match result {
+                    Ok(_) => {
+                        self.saved_revision = self.saved_revision.max(revision);
+                        if !self.is_dirty() {
+                            self.dirty_since = None;
+                            if let Err(e) = self.journal.clear() {
+                                return self.update(Message::DBError(
+                                    "Journal nicht geleert".to_string(),
+                                    e,
+                                ));
+                            }
+                        }
+                        if manual {
+                            self.notify(Toast::new(
+                                "Gespeichert",
+                                "Datenbank und Einstellungen gespeichert",
+                                Status::Success,
+                            ));
+                        }
+                    }
+                    Err(e) => {
+                        // Try again after the next delay instead of every tick
+                        self.last_change = Instant::now();
+                        return self.update(Message::DBError("Nicht gespeichert".to_string(), e));
                     }
-                    None => (),
                 }
-                return Task::perform(save_all(self.db.clone(), sets), |r| match r {
-                    Ok(_) => Message::Notify(Toast::new(
-                        "Success",
-                        "DB and Settings saved",
-                        Status::Success,
-                    )),
-                    Err(_) => Message::Notify(Toast::new(
-                        "Failed",
-                        "DB and Settings not saved",
-                        Status::Danger,
-                    )),
-                });
+            }
+            Message::DBError(title, e) => {
+                self.status_text = format!("{}: {}", title, e);
+                self.notify(Toast::new(&title, &e.to_string(), Status::Danger));
             }
             Message::Notify(t) => self.notify(t),
             Message::CloseToast(index) => {
                 self.toasts.remove(index);
             }
+            Message::ToastAction(index, id) => {
+                if index < self.toasts.len() {
+                    self.toasts.remove(index);
+                }
+                // Only the newest step can be taken back from its toast,
+                // older ones would silently revert the later changes too
+                if self.history.last_id() == Some(id) {
+                    self.undo();
+                } else {
+                    self.notify(Toast::new(
+                        "Nicht rückgängig gemacht",
+                        "Seither gab es weitere Änderungen, mit Strg+Z schrittweise zurückgehen",
+                        Status::Danger,
+                    ));
+                }
+            }
+            Message::Undo => self.undo(),
+            Message::Redo => self.redo(),
             Message::Nothing => (),
             Message::Abs(msg) => return absences::handle_absences(msg, self),
             Message::MainMenu(msg) => return menu::update_menu(self, msg),
             Message::List(msg) => return list::update_list(self, msg),
+            Message::Roster(msg) => return roster::update_roster(self, msg),
+            Message::Subjects(msg) => return subjects::update_subjects(self, msg),
+            Message::Timetable(msg) => return timetable::update_timetable(self, msg),
+            Message::Backup(msg) => return backup::update_backup(self, msg),
+            Message::Merge(msg) => return merge::update_merge(self, msg),
+            Message::Check(msg) => return check::update_check(self, msg),
+            Message::Retention(msg) => return retention::update_retention(self, msg),
+            Message::Excuses(msg) => return excuses::update_excuses(self, msg),
+            Message::Span(msg) => return spans::update_span(self, msg),
+            Message::Schedule(msg) => return schedule::update_schedule(self, msg),
+            Message::Calendar(msg) => return calendar::update_calendar(self, msg),
+            Message::Unlock(msg) => return unlock::update_unlock(self, msg),
         }
         Task::none()
     }
@@ -336,8 +813,16 @@ impl App {
             ViewControl::SETTINGS => settings::settings_view(self),
             ViewControl::MENU => menu::menu_view(self),
             ViewControl::ABSENCES => absences::absences_view(self),
+            ViewControl::CHECK => check::check_view(self),
+            ViewControl::UNLOCK => unlock::unlock_view(self),
+            ViewControl::EXCUSES => excuses::excuses_view(self),
         };
-        toast::Manager::new(content, &self.toasts, Message::CloseToast)
+        toast::Manager::new(
+            content,
+            &self.toasts,
+            Message::CloseToast,
+            Message::ToastAction,
+        )
             .timeout(3)
             .into()
     }
@@ -353,6 +838,165 @@ impl App {
         self.toasts.push(t);
 
     }
+
+    /// Applies a change to the database, hands it to the storage and records
+    /// it for undo. Returns the id of the history step, if anything changed.
+    pub fn commit(&mut self, change: db::Change) -> Option<u64> {
+        let backward = self.db.inverse(&change);
+        self.write(change.clone());
+        backward.map(|b| self.history.record(change, b))
+    }
+
+    /// Like `commit`, with a toast that offers to take the change back
+    pub fn commit_undoable(&mut self, change: db::Change, title: &str, body: &str) {
+        if let Some(id) = self.commit(change) {
+            self.notify(
+                Toast::new(title, body, Status::Secondary).with_action("Rückgängig", id),
+            );
+        }
+    }
+
+    fn write(&mut self, change: db::Change) {
+        let stored = match self.db_locked {
+            true => Ok(()),
+            false => self.storage.apply(&change),
+        };
+        if let Err(e) = stored {
+            self.status_text = format!("Änderung nicht gespeichert: {}", e);
+            self.notify(Toast::new(
+                "Änderung nicht gespeichert",
+                &e.to_string(),
+                Status::Danger,
+            ));
+        }
+        if !self.db_locked && !self.storage.saves_changes() {
+            if let Err(e) = self.journal.append(&change) {
+                self.status_text = format!("Journal: {}", e);
+            }
+            self.mark_changed();
+        }
+        self.db.apply(change);
+        stats::refresh(self);
+    }
+
+    fn undo(&mut self) {
+        if let Some(step) = self.history.undo() {
+            self.write(step.backward);
+            self.fix_selection();
+            self.notify(Toast::new("Rückgängig", step.label, Status::Secondary));
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(step) = self.history.redo() {
+            self.write(step.forward);
+            self.fix_selection();
+            self.notify(Toast::new("Wiederholt", step.label, Status::Secondary));
+        }
+    }
+
+    /// Undo, redo or a restore can take away the class, person or entry on screen
+    pub fn fix_selection(&mut self) {
+        if self.cohort.is_none_or(|c| self.db.cohort(c).is_none()) {
+            self.cohort = self.db.cohorts.first().map(|c| c.id);
+        }
+        if self.editing.is_some_and(|id| self.db.entry(id).is_none()) {
+            self.editing = None;
+            self.add_entry.id = 0;
+        }
+        self.refresh_pickers();
+    }
+
+    /// Something that is saved together changed, the database or the settings
+    pub fn mark_changed(&mut self) {
+        self.revision += 1;
+        self.last_change = Instant::now();
+        self.dirty_since.get_or_insert(self.last_change);
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.revision;
+        self.dirty_since = None;
+    }
+
+    /// True while changes exist that are only in memory and the journal
+    pub fn is_dirty(&self) -> bool {
+        self.revision != self.saved_revision
+    }
+
+    fn save(&mut self, manual: bool) -> Task<Message> {
+        self.saving = true;
+        let revision = self.revision;
+        let sets = self.current_settings();
+        Task::perform(
+            save_all(self.storage.clone(), self.db_for_saving(), sets),
+            move |result| Message::Saved {
+                revision,
+                manual,
+                result,
+            },
+        )
+    }
+
+    fn current_settings(&self) -> settings::Settings {
+        let mut sets = self.settings.clone();
+        if let Some(theme) = &self.selected_theme {
+            sets.theme = theme.to_string();
+        }
+        sets
+    }
+
+    fn db_for_saving(&self) -> Option<DataBase> {
+        match self.db_locked {
+            true => None,
+            false => Some(self.db.clone()),
+        }
+    }
+
+    /// The lesson a late or an absence recorded now belongs to
+    pub fn current_lesson(&self) -> time::LessonSlot {
+        time::current_lesson(&self.settings.schedules, &self.settings.calendar)
+    }
+
+    /// The lesson picked in the late form, on one held on its date
+    fn entry_slot(&self) -> time::LessonSlot {
+        let (date, t) = (self.add_entry.date, self.add_entry.lesson_time);
+        time::snap(&self.settings.schedules, date, t)
+    }
+
+    fn set_entry_slot(&mut self, slot: time::LessonSlot) {
+        self.add_entry.date = slot.date;
+        self.add_entry.lesson_time = slot.start(&self.settings.schedules);
+    }
+
+    /// An empty absence form for the current class and lesson, with the
+    /// subject from the timetable if it has one
+    pub fn fresh_absence(&self) -> db::LessonAbs {
+        let now = self.current_lesson();
+        let start = now.start(&self.settings.schedules);
+        let mut abs = db::LessonAbs::new_smart(&self.db, self.cohort, now.date, start);
+        if let Some(plan) = timetable::planned(&self.db, self.cohort, now) {
+            abs.lesson = plan.subject.unwrap_or(abs.lesson);
+            abs.first_lesson = plan.first_lesson;
+        }
+        abs
+    }
+
+    /// Rebuilds the person and subject pickers after roster or catalogue changed
+    pub fn refresh_pickers(&mut self) {
+        self.combo = combo_box::State::new(self.db.roster(self.cohort));
+        self.sel_pers = self
+            .sel_pers
+            .as_ref()
+            .and_then(|p| self.db.student(p.id).cloned())
+            .filter(|p| Some(p.cohort) == self.cohort);
+        self.combo2 = combo_box::State::new(self.db.active_subjects(self.cohort));
+        self.sel_lesson = self
+            .sel_lesson
+            .as_ref()
+            .and_then(|l| self.db.subject(l.id).cloned())
+            .filter(|l| Some(l.cohort) == self.cohort);
+    }
 }
 impl Default for App {
     fn default() -> Self {
@@ -1,6 +1,5 @@
 use crate::bootstrap::ICON_FONT;
 use crate::bootstrap::*;
-use crate::db::Class;
 use crate::themes::{self, text_fg, text_fg_succes};
 use crate::{App, Message, ViewControl};
 use iced::widget::text::Alignment;
@@ -71,7 +70,7 @@ pub fn menu_view(app: &App) -> Element<Message> {
     );
 
     let new = main_menu_button(
-        Message::GoView(ViewControl::ADD),
+        Message::Add,
         Bootstrap::Plus,
         MenuStyle::Default,
         "Neue Verspätung"
@@ -91,6 +90,13 @@ pub fn menu_view(app: &App) -> Element<Message> {
         "Absenzen erfassen"
     );
 
+    let excuses = main_menu_button(
+        Message::GoView(ViewControl::EXCUSES),
+        Bootstrap::EnvelopeExclamationFill,
+        MenuStyle::Default,
+        "Entschuldigungen"
+    );
+
     let exit = button(
         text(icon_to_string(Bootstrap::BoxArrowRight))
             .align_y(alignment::Vertical::Center)
@@ -114,13 +120,61 @@ pub fn menu_view(app: &App) -> Element<Message> {
         t
     });
 
+    let cohort = row![
+        text(icon_to_string(Bootstrap::PeopleFill))
+            .font(ICON_FONT)
+            .style(text::primary)
+            .size(20),
+        pick_list(
+            app.db.cohorts.clone(),
+            app.cohort.and_then(|c| app.db.cohort(c).cloned()),
+            Message::SelectCohort
+        )
+        .placeholder("Keine Klasse"),
+        button(
+            text(icon_to_string(Bootstrap::ArrowCounterclockwise))
+                .font(ICON_FONT)
+                .size(20),
+        )
+        .on_press_maybe(app.history.can_undo().then_some(Message::Undo))
+        .style(button::text),
+        button(
+            text(icon_to_string(Bootstrap::ArrowClockwise))
+                .font(ICON_FONT)
+                .size(20),
+        )
+        .on_press_maybe(app.history.can_redo().then_some(Message::Redo))
+        .style(button::text),
+        if app.is_dirty() {
+            row![
+                text(icon_to_string(Bootstrap::CircleFill))
+                    .font(ICON_FONT)
+                    .style(text::danger),
+                text("Ungespeichert").style(text::danger),
+            ]
+        } else {
+            row![
+                text(icon_to_string(Bootstrap::CheckCircleFill))
+                    .font(ICON_FONT)
+                    .style(text_fg_succes),
+                text("Gespeichert").style(text_fg_succes),
+            ]
+        }
+        .spacing(5)
+        .align_y(alignment::Vertical::Center),
+    ]
+    .spacing(10)
+    .align_y(alignment::Vertical::Center);
+
     column![
         vertical_space(),
         text("Verspätungsmanager 4002").size(30).style(text::primary),
-        row![new, absences, list, stats, settings, exit]
+        cohort,
+        row![new, absences, excuses, list, stats, settings, exit]
             .spacing(10)
             .padding(5),
         text(&app.menu.title),
+        text(&app.status_text).style(text::danger),
         vertical_space(),
     ]
     .spacing(10)
@@ -0,0 +1,571 @@
+use crate::bootstrap::*;
+use crate::db::{
+    AbsenceRecord, Change, Cohort, CohortId, DataBase, DataBaseError, Entry, ExcuseStatus,
+    LessonAbs, Student, StudentId, Subject, SubjectId,
+};
+use crate::storage;
+use crate::themes::{self, styled_button, ColorType};
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use iced::widget::{column, row, text, text_input, toggler};
+use iced::{Alignment, Element, Task};
+use std::collections::HashMap;
+
+/// Two records for the same person or class and lesson that do not agree
+#[derive(Debug, Clone)]
+pub struct Conflict<T> {
+    pub ours: T,
+    pub theirs: T,
+    pub take_theirs: bool,
+}
+
+/// What merging another database into this one would change. Classes,
+/// students and subjects are matched by name since the ids of two machines
+/// have nothing to do with each other.
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    pub new_cohorts: Vec<Cohort>,
+    pub new_students: Vec<Student>,
+    pub new_subjects: Vec<Subject>,
+    pub new_entries: Vec<Entry>,
+    pub duplicate_entries: usize,
+    pub entry_conflicts: Vec<Conflict<Entry>>,
+    pub new_absences: Vec<LessonAbs>,
+    pub duplicate_absences: usize,
+    pub absence_conflicts: Vec<Conflict<LessonAbs>>,
+    /// Records pointing to a student or subject the other file does not know
+    pub skipped: usize,
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+/// Records of a lesson by student, without the span ids that only mean
+/// something within their own file
+fn comparable(records: &[AbsenceRecord]) -> Vec<AbsenceRecord> {
+    let mut records: Vec<AbsenceRecord> = records
+        .iter()
+        .map(|r| AbsenceRecord {
+            span: None,
+            ..r.clone()
+        })
+        .collect();
+    records.sort_by_key(|r| r.student);
+    records
+}
+
+impl DataBase {
+    /// Compares `other` against this database, nothing is changed yet
+    pub fn merge(&self, other: &DataBase) -> MergeReport {
+        let mut report = MergeReport::default();
+
+        let mut cohorts: HashMap<CohortId, CohortId> = HashMap::new();
+        let mut next = self.next_cohort_id();
+        for c in &other.cohorts {
+            let id = match self.cohorts.iter().find(|o| same_name(&o.name, &c.name)) {
+                Some(o) => o.id,
+                None => {
+                    report.new_cohorts.push(Cohort {
+                        id: next,
+                        name: c.name.clone(),
+                    });
+                    next += 1;
+                    next - 1
+                }
+            };
+            cohorts.insert(c.id, id);
+        }
+
+        let mut students: HashMap<StudentId, StudentId> = HashMap::new();
+        let mut next = self.next_student_id();
+        for s in &other.students {
+            let Some(&cohort) = cohorts.get(&s.cohort) else {
+                continue;
+            };
+            let id = match self
+                .students
+                .iter()
+                .find(|o| o.cohort == cohort && same_name(&o.full_name(), &s.full_name()))
+            {
+                Some(o) => o.id,
+                None => {
+                    report.new_students.push(Student {
+                        id: next,
+                        cohort,
+                        ..s.clone()
+                    });
+                    next += 1;
+                    next - 1
+                }
+            };
+            students.insert(s.id, id);
+        }
+
+        let mut subjects: HashMap<SubjectId, SubjectId> = HashMap::new();
+        let mut next = self.next_subject_id();
+        for s in &other.subjects {
+            let Some(&cohort) = cohorts.get(&s.cohort) else {
+                continue;
+            };
+            let id = match self
+                .subjects
+                .iter()
+                .find(|o| o.cohort == cohort && same_name(&o.name, &s.name))
+            {
+                Some(o) => o.id,
+                None => {
+                    report.new_subjects.push(Subject {
+                        id: next,
+                        cohort,
+                        // The other timetable would clash with the one here
+                        slots: vec![],
+                        ..s.clone()
+                    });
+                    next += 1;
+                    next - 1
+                }
+            };
+            subjects.insert(s.id, id);
+        }
+
+        for e in &other.data {
+            let (Some(&person), Some(&lesson)) = (students.get(&e.person), subjects.get(&e.lesson))
+            else {
+                report.skipped += 1;
+                continue;
+            };
+            let e = Entry {
+                person,
+                lesson,
+                ..e.clone()
+            };
+            let slot: Vec<&Entry> = self
+                .data
+                .iter()
+                .filter(|o| {
+                    o.person == e.person && o.date == e.date && o.lesson_time == e.lesson_time
+                })
+                .collect();
+            if slot.iter().any(|o| {
+                o.lesson == e.lesson
+                    && o.delay_min == e.delay_min
+                    && o.first_lesson == e.first_lesson
+            }) {
+                report.duplicate_entries += 1;
+            } else if let Some(ours) = slot.first() {
+                report.entry_conflicts.push(Conflict {
+                    ours: (*ours).clone(),
+                    theirs: e,
+                    take_theirs: false,
+                });
+            } else {
+                report.new_entries.push(e);
+            }
+        }
+
+        for a in &other.absences {
+            let (Some(&cohort), Some(&lesson)) = (cohorts.get(&a.cohort), subjects.get(&a.lesson))
+            else {
+                report.skipped += 1;
+                continue;
+            };
+            let mut present: Vec<StudentId> = a
+                .present
+                .iter()
+                .filter_map(|p| students.get(p).copied())
+                .collect();
+            present.sort();
+            // Span ids only mean something within their own file, so merged
+            // lessons become single ones
+            let records = a
+                .records
+                .iter()
+                .filter_map(|r| {
+                    Some(AbsenceRecord {
+                        student: *students.get(&r.student)?,
+                        span: None,
+                        ..r.clone()
+                    })
+                })
+                .collect();
+            let a = LessonAbs {
+                cohort,
+                lesson,
+                present,
+                records,
+                span: None,
+                ..a.clone()
+            };
+            let slot = self.absences.iter().find(|o| {
+                o.cohort == a.cohort && o.date == a.date && o.lesson_time == a.lesson_time
+            });
+            match slot {
+                Some(o) => {
+                    let mut ours = o.present.clone();
+                    ours.sort();
+                    // A status or note set on only one of the machines is a
+                    // conflict as well, it must not get lost silently
+                    if o.lesson == a.lesson
+                        && ours == a.present
+                        && comparable(&o.records) == comparable(&a.records)
+                    {
+                        report.duplicate_absences += 1;
+                    } else {
+                        report.absence_conflicts.push(Conflict {
+                            ours: o.clone(),
+                            theirs: a,
+                            take_theirs: false,
+                        });
+                    }
+                }
+                None => report.new_absences.push(a),
+            }
+        }
+        report
+    }
+}
+
+impl MergeReport {
+    pub fn is_empty(&self) -> bool {
+        self.new_cohorts.is_empty()
+            && self.new_students.is_empty()
+            && self.new_subjects.is_empty()
+            && self.new_entries.is_empty()
+            && self.new_absences.is_empty()
+            && !self.entry_conflicts.iter().any(|c| c.take_theirs)
+            && !self.absence_conflicts.iter().any(|c| c.take_theirs)
+    }
+
+    /// Carries the choices made on an older report over, if it still
+    /// describes the same conflicts
+    fn take_choices(&mut self, old: &MergeReport) -> bool {
+        if self.entry_conflicts.len() != old.entry_conflicts.len()
+            || self.absence_conflicts.len() != old.absence_conflicts.len()
+        {
+            return false;
+        }
+        for (c, o) in self.entry_conflicts.iter_mut().zip(&old.entry_conflicts) {
+            c.take_theirs = o.take_theirs;
+        }
+        for (c, o) in self.absence_conflicts.iter_mut().zip(&old.absence_conflicts) {
+            c.take_theirs = o.take_theirs;
+        }
+        true
+    }
+
+    /// `db` with everything new added and the chosen conflicts replaced
+    pub fn apply_to(&self, db: &DataBase) -> DataBase {
+        let mut db = db.clone();
+        db.cohorts.extend(self.new_cohorts.iter().cloned());
+        db.students.extend(self.new_students.iter().cloned());
+        db.subjects.extend(self.new_subjects.iter().cloned());
+        for e in &self.new_entries {
+            let id = db.next_entry_id();
+            db.data.push(Entry { id, ..e.clone() });
+        }
+        for c in self.entry_conflicts.iter().filter(|c| c.take_theirs) {
+            db.apply(Change::UpdateEntry(Entry {
+                id: c.ours.id,
+                ..c.theirs.clone()
+            }));
+        }
+        for a in &self.new_absences {
+            let id = db.next_absence_id();
+            db.absences.push(LessonAbs { id, ..a.clone() });
+        }
+        for c in self.absence_conflicts.iter().filter(|c| c.take_theirs) {
+            // Keep the lesson in the multi-day absences it belongs to here
+            let records = c
+                .theirs
+                .records
+                .iter()
+                .map(|r| AbsenceRecord {
+                    span: c.ours.record(r.student).and_then(|o| o.span),
+                    ..r.clone()
+                })
+                .collect();
+            db.apply(Change::UpdateAbsence(LessonAbs {
+                id: c.ours.id,
+                span: c.ours.span,
+                records,
+                ..c.theirs.clone()
+            }));
+        }
+        db
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MergeState {
+    path: String,
+    other: Option<DataBase>,
+    report: Option<MergeReport>,
+}
+
+#[derive(Debug, Clone)]
+pub enum MergeMsg {
+    Path(String),
+    Browse,
+    Picked(Option<String>),
+    Compare,
+    Loaded(Result<DataBase, DataBaseError>),
+    ToggleEntry(usize),
+    ToggleAbsence(usize),
+    Apply,
+    Cancel,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn pick_file() -> Option<String> {
+    rfd::AsyncFileDialog::new()
+        .add_filter("Datenbank", &["json", "sqlite"])
+        .pick_file()
+        .await
+        .map(|f| f.path().to_string_lossy().to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn pick_file() -> Option<String> {
+    None
+}
+
+pub fn update_merge(app: &mut App, msg: MergeMsg) -> Task<Message> {
+    match msg {
+        MergeMsg::Path(p) => app.merge.path = p,
+        MergeMsg::Browse => {
+            return Task::perform(pick_file(), |p| Message::Merge(MergeMsg::Picked(p)))
+        }
+        MergeMsg::Picked(Some(p)) => {
+            app.merge.path = p;
+            return Task::done(Message::Merge(MergeMsg::Compare));
+        }
+        MergeMsg::Picked(None) => (),
+        MergeMsg::Compare => {
+            let path = app.merge.path.trim().to_string();
+            if path.is_empty() {
+                return Task::none();
+            }
+            return Task::perform(async move { storage::read_only(&path) }, |r| {
+                Message::Merge(MergeMsg::Loaded(r))
+            });
+        }
+        MergeMsg::Loaded(Ok(other)) => {
+            app.merge.report = Some(app.db.merge(&other));
+            app.merge.other = Some(other);
+        }
+        MergeMsg::Loaded(Err(e)) => {
+            return Task::done(Message::DBError("Nicht geladen".to_string(), e))
+        }
+        MergeMsg::ToggleEntry(i) => {
+            if let Some(c) = app
+                .merge
+                .report
+                .as_mut()
+                .and_then(|r| r.entry_conflicts.get_mut(i))
+            {
+                c.take_theirs = !c.take_theirs;
+            }
+        }
+        MergeMsg::ToggleAbsence(i) => {
+            if let Some(c) = app
+                .merge
+                .report
+                .as_mut()
+                .and_then(|r| r.absence_conflicts.get_mut(i))
+            {
+                c.take_theirs = !c.take_theirs;
+            }
+        }
+        MergeMsg::Apply => {
+            let (Some(other), Some(old)) = (&app.merge.other, &app.merge.report) else {
+                return Task::none();
+            };
+            // Compare again, the data may have changed since the report was made
+            let mut report = app.db.merge(other);
+            if !report.take_choices(old) {
+                app.merge.report = Some(report);
+                app.notify(Toast::new(
+                    "Nicht zusammengeführt",
+                    "Die Daten haben sich seit dem Vergleich geändert, bitte Bericht prüfen",
+                    Status::Danger,
+                ));
+                return Task::none();
+            }
+            if report.is_empty() {
+                app.notify(Toast::new(
+                    "Nichts zu tun",
+                    "Die Datei enthält keine neuen Daten",
+                    Status::Secondary,
+                ));
+            } else {
+                let merged = report.apply_to(&app.db);
+                app.commit_undoable(
+                    Change::Replace(Box::new(merged)),
+                    "Zusammengeführt",
+                    &format!(
+                        "{} Verspätungen und {} Absenzen übernommen",
+                        report.new_entries.len(),
+                        report.new_absences.len()
+                    ),
+                );
+                app.fix_selection();
+            }
+            app.merge = MergeState::default();
+        }
+        MergeMsg::Cancel => app.merge = MergeState::default(),
+    }
+    Task::none()
+}
+
+pub fn merge_view(app: &App) -> Element<Message> {
+    let mut col = column![
+        text("Zusammenführen").size(22).style(themes::text_fg),
+        text("Übernimmt Verspätungen und Absenzen aus einer zweiten Datenbank")
+            .style(themes::text_fg_sec),
+        row![
+            text_input("Pfad zur zweiten Datei", &app.merge.path)
+                .on_input(|s| Message::Merge(MergeMsg::Path(s)))
+                .on_submit(Message::Merge(MergeMsg::Compare)),
+            styled_button(
+                Bootstrap::Folder,
+                "Durchsuchen",
+                Message::Merge(MergeMsg::Browse),
+                ColorType::Secondary,
+            ),
+            styled_button(
+                Bootstrap::Search,
+                "Vergleichen",
+                Message::Merge(MergeMsg::Compare),
+                ColorType::Primary,
+            ),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+    ]
+    .spacing(5);
+
+    let Some(report) = &app.merge.report else {
+        return col.into();
+    };
+
+    col = col.push(text(format!(
+        "Neu: {} Verspätungen, {} Absenzen, {} Klassen, {} Schüler, {} Fächer",
+        report.new_entries.len(),
+        report.new_absences.len(),
+        report.new_cohorts.len(),
+        report.new_students.len(),
+        report.new_subjects.len()
+    )));
+    col = col.push(
+        text(format!(
+            "Bereits vorhanden: {} Verspätungen, {} Absenzen",
+            report.duplicate_entries, report.duplicate_absences
+        ))
+        .style(themes::text_fg_sec),
+    );
+    if report.skipped > 0 {
+        col = col.push(
+            text(format!(
+                "{} Einträge verweisen auf unbekannte Personen oder Fächer und werden übersprungen",
+                report.skipped
+            ))
+            .style(themes::text_fg_danger),
+        );
+    }
+
+    if !report.entry_conflicts.is_empty() || !report.absence_conflicts.is_empty() {
+        col = col.push(
+            text("Widersprüche, bitte prüfen").size(18).style(themes::text_fg_danger),
+        );
+    }
+    for (i, c) in report.entry_conflicts.iter().enumerate() {
+        let describe = |e: &Entry| {
+            format!(
+                "{}, {} min{}",
+                app.db.subject_name(e.lesson),
+                e.delay_min,
+                if e.first_lesson { ", erste Lektion" } else { "" }
+            )
+        };
+        col = col.push(
+            row![
+                text(format!(
+                    "{} am {} um {}",
+                    app.db.student_name(c.ours.person),
+                    c.ours.date.format("%d.%m.%Y"),
+                    c.ours.lesson_time.format("%H:%M")
+                )),
+                text(format!("hier: {}", describe(&c.ours))).style(themes::text_fg_sec),
+                text(format!("dort: {}", describe(&c.theirs))).style(themes::text_fg_sec),
+                toggler(c.take_theirs)
+                    .on_toggle(move |_| Message::Merge(MergeMsg::ToggleEntry(i)))
+                    .label("dort übernehmen"),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+    }
+    for (i, c) in report.absence_conflicts.iter().enumerate() {
+        let describe = |a: &LessonAbs| {
+            let mut parts = vec![
+                app.db.subject_name(a.lesson),
+                format!("{} anwesend", a.present.len()),
+            ];
+            for status in [
+                ExcuseStatus::Open,
+                ExcuseStatus::Excused,
+                ExcuseStatus::Unexcused,
+            ] {
+                let n = a.records.iter().filter(|r| r.status == status).count();
+                if n > 0 {
+                    parts.push(format!("{} {}", n, status));
+                }
+            }
+            let notes = a.records.iter().filter(|r| !r.note.is_empty()).count();
+            if notes > 0 {
+                parts.push(format!("{} Notizen", notes));
+            }
+            parts.join(", ")
+        };
+        col = col.push(
+            row![
+                text(format!(
+                    "Absenz {} am {} um {}",
+                    app.db
+                        .cohort(c.ours.cohort)
+                        .map(|c| c.name.clone())
+                        .unwrap_or_default(),
+                    c.ours.date.format("%d.%m.%Y"),
+                    c.ours.lesson_time.format("%H:%M")
+                )),
+                text(format!("hier: {}", describe(&c.ours))).style(themes::text_fg_sec),
+                text(format!("dort: {}", describe(&c.theirs))).style(themes::text_fg_sec),
+                toggler(c.take_theirs)
+                    .on_toggle(move |_| Message::Merge(MergeMsg::ToggleAbsence(i)))
+                    .label("dort übernehmen"),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+    }
+
+    col.push(
+        row![
+            styled_button(
+                Bootstrap::CheckSquareFill,
+                "Zusammenführen",
+                Message::Merge(MergeMsg::Apply),
+                ColorType::Succes,
+            ),
+            styled_button(
+                Bootstrap::XSquareFill,
+                "Abbrechen",
+                Message::Merge(MergeMsg::Cancel),
+                ColorType::Secondary,
+            ),
+        ]
+        .spacing(5),
+    )
+    .into()
+}
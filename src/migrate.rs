@@ -0,0 +1,430 @@
+use crate::calendar::Calendar;
+use crate::db::{DataBaseError, SUBJECT_COLORS};
+use crate::{settings, time};
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+
+/// Version of the db.json layout written by this build
+pub const SCHEMA_VERSION: u32 = 8;
+
+/// `MIGRATIONS[i]` upgrades a document from version `i + 1` to `i + 2`
+const MIGRATIONS: [fn(&mut Value); 7] = [
+    v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7, v7_to_v8,
+];
+
+/// Files written before `schema_version` existed count as version 1
+pub fn version_of(doc: &Value) -> u32 {
+    doc.get("schema_version")
+        .and_then(Value::as_u64)
+        .map(|v| v as u32)
+        .unwrap_or(1)
+}
+
+/// Runs every migration between the version of `doc` and `SCHEMA_VERSION`.
+/// Documents written by a newer build are refused instead of guessed at.
+pub fn migrate(doc: &mut Value) -> Result<(), DataBaseError> {
+    let version = version_of(doc).max(1);
+    if version > SCHEMA_VERSION {
+        return Err(DataBaseError::UnsupportedVersion(version));
+    }
+    if !doc.is_object() {
+        // Let serde report what is wrong with it
+        return Ok(());
+    }
+    for step in &MIGRATIONS[(version as usize - 1)..] {
+        step(doc);
+    }
+    doc["schema_version"] = Value::from(SCHEMA_VERSION);
+    Ok(())
+}
+
+/// Roster, subject catalogue, classes and entry ids
+fn v1_to_v2(doc: &mut Value) {
+    // Version 1 covers every file from before the version field, so each
+    // step checks for itself whether the document still needs it
+    if doc.get("students").is_none() {
+        upgrade_legacy_roster(doc);
+    }
+    if doc.get("subjects").is_none() {
+        upgrade_legacy_subjects(doc);
+    }
+    if doc.get("cohorts").is_none() {
+        upgrade_single_cohort(doc);
+    }
+    upgrade_entry_ids(doc);
+}
+
+/// Absences get ids so they can be stored and changed one by one
+fn v2_to_v3(doc: &mut Value) {
+    if let Some(absences) = doc.get_mut("absences").and_then(Value::as_array_mut) {
+        for (i, a) in absences.iter_mut().enumerate() {
+            a["id"] = Value::from(i as u64 + 1);
+        }
+    }
+}
+
+/// Entries get a reason, a note and the excused flag, all old lates count
+/// as unexcused like before
+fn v3_to_v4(doc: &mut Value) {
+    if let Some(entries) = doc.get_mut("data").and_then(Value::as_array_mut) {
+        for e in entries.iter_mut().filter(|e| e.is_object()) {
+            e["reason"] = Value::from("Unknown");
+            e["note"] = Value::from("");
+            e["excused"] = Value::from(false);
+        }
+    }
+}
+
+/// Archived absence counts and pseudonymised students. Both are optional, the
+/// bump only keeps older builds from dropping them when they save.
+fn v4_to_v5(_doc: &mut Value) {}
+
+/// Absences get a record for everyone of the class that was not present,
+/// which is who counted as missing before. Nobody kept track of excuses
+/// then, so records whose deadline has already passed are settled as excused
+/// instead of showing up as overdue. The deadline counts the default number
+/// of school days, the days off of the settings aren't known here.
+fn v5_to_v6(doc: &mut Value) {
+    let today = time::get_today();
+    let students: Vec<(Value, Value)> = doc
+        .get("students")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|s| s.get("pseudonym") != Some(&Value::Bool(true)))
+        .map(|s| (s["id"].clone(), s["cohort"].clone()))
+        .collect();
+    let Some(absences) = doc.get_mut("absences").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for a in absences.iter_mut().filter(|a| a.is_object()) {
+        let has_records = a
+            .get("records")
+            .and_then(Value::as_array)
+            .is_some_and(|r| !r.is_empty());
+        if has_records || a.get("archived_absent").is_some_and(|n| !n.is_null()) {
+            continue;
+        }
+        let present = a.get("present").and_then(Value::as_array).cloned();
+        let deadline = a
+            .get("date")
+            .and_then(Value::as_str)
+            .and_then(|d| d.parse::<NaiveDate>().ok())
+            .map(|d| {
+                time::add_school_days(d, settings::default_excuse_days(), &Calendar::default())
+            });
+        let records: Vec<Value> = students
+            .iter()
+            .filter(|(id, cohort)| {
+                *cohort == a["cohort"] && !present.as_ref().is_some_and(|p| p.contains(id))
+            })
+            .map(|(id, _)| {
+                json!({
+                    "student": id,
+                    "status": if deadline.is_some_and(|d| d < today) { "Excused" } else { "Open" },
+                    "note": "",
+                    "deadline": deadline.map_or(a["date"].clone(), |d| json!(d)),
+                })
+            })
+            .collect();
+        a["records"] = Value::Array(records);
+    }
+}
+
+/// Lessons and records of multi-day absences carry the id of their span.
+/// Older absences are single ones, so there is nothing to fill in.
+fn v6_to_v7(_doc: &mut Value) {}
+
+/// Subjects carry their timetable slots, which default to none. Older files
+/// have no timetable, so nothing has to be filled in.
+fn v7_to_v8(_doc: &mut Value) {}
+
+/// Pupils of the roster that used to be compiled into the binary as `enum Class`
+const LEGACY_ROSTER: [&str; 25] = [
+    "Nicole", "Sophia", "Emily", "Leander", "Suya", "Jan", "Liam", "Evelin", "Isabelle", "Anina",
+    "Carlo", "Levin", "Anne", "Kilian", "Jonah", "Ida", "Neda", "Antonie", "Laurin", "Marija",
+    "Raphael", "Elena", "Luis", "Mia", "Marie",
+];
+
+/// Rewrites a db.json from before the roster was stored as data: persons were
+/// saved by their enum name, they now become `Student` records referenced by id.
+fn upgrade_legacy_roster(doc: &mut Value) {
+    let mut names: Vec<String> = LEGACY_ROSTER.iter().map(|n| n.to_string()).collect();
+    if let Some(entries) = doc.get("data").and_then(Value::as_array) {
+        for e in entries {
+            if let Some(name) = e.get("person").and_then(Value::as_str) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    if let Some(absences) = doc.get("absences").and_then(Value::as_array) {
+        for a in absences {
+            if let Some(present) = a.get("present").and_then(Value::as_array) {
+                names.extend(present.iter().filter_map(Value::as_str).map(String::from));
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    let id_of = |name: &str| -> Value {
+        match names.iter().position(|n| n == name) {
+            Some(i) => Value::from(i as u32 + 1),
+            None => Value::Null,
+        }
+    };
+
+    if let Some(entries) = doc.get_mut("data").and_then(Value::as_array_mut) {
+        for e in entries {
+            if let Some(name) = e.get("person").and_then(Value::as_str).map(String::from) {
+                e["person"] = id_of(&name);
+            }
+        }
+    }
+    if let Some(absences) = doc.get_mut("absences").and_then(Value::as_array_mut) {
+        for a in absences {
+            if let Some(present) = a.get_mut("present").and_then(Value::as_array_mut) {
+                for p in present.iter_mut() {
+                    if let Some(name) = p.as_str().map(String::from) {
+                        *p = id_of(&name);
+                    }
+                }
+            }
+        }
+    }
+    doc["students"] = Value::Array(
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, n)| json!({ "id": i + 1, "first_name": n, "last_name": "", "alias": null }))
+            .collect(),
+    );
+}
+
+/// Subjects of the old `enum Lesson`: name, short code and whether it was
+/// still listed in `Lesson::all()`
+const LEGACY_SUBJECTS: [(&str, &str, bool); 22] = [
+    ("Mathe", "M", true),
+    ("Bio", "B", true),
+    ("Deutsch", "D", true),
+    ("PFPhysik", "PFP", true),
+    ("Physik", "P", false),
+    ("Franzoesisch", "F", true),
+    ("Italienisch", "I", true),
+    ("Geschichte", "G", false),
+    ("PFGeschichte", "PFG", true),
+    ("Englisch", "E", true),
+    ("PFEnglisch", "PFE", true),
+    ("Chemie", "C", false),
+    ("EFChemie", "EFC", true),
+    ("Sport", "S", true),
+    ("BG", "BG", false),
+    ("EFBG", "EFBG", true),
+    ("Musik", "MU", false),
+    ("Griechisch", "GR", true),
+    ("Geographie", "GG", false),
+    ("EFGeographie", "EFGG", true),
+    ("EFPhilosophie", "EFPP", true),
+    ("EFSport", "EFS", true),
+];
+
+/// Rewrites a db.json from before the subject catalogue existed: subjects
+/// were saved by their enum name and become `Subject` records referenced by id.
+fn upgrade_legacy_subjects(doc: &mut Value) {
+    let id_of = |name: &str| -> Value {
+        match LEGACY_SUBJECTS.iter().position(|l| l.0 == name) {
+            Some(i) => Value::from(i as u32 + 1),
+            None => Value::Null,
+        }
+    };
+    for list in ["data", "absences"] {
+        if let Some(items) = doc.get_mut(list).and_then(Value::as_array_mut) {
+            for item in items {
+                if let Some(name) = item.get("lesson").and_then(Value::as_str).map(String::from) {
+                    item["lesson"] = id_of(&name);
+                }
+            }
+        }
+    }
+    doc["subjects"] = Value::Array(
+        LEGACY_SUBJECTS
+            .iter()
+            .enumerate()
+            .map(|(i, (name, code, active))| {
+                json!({
+                    "id": i + 1,
+                    "name": name,
+                    "code": code,
+                    "color": SUBJECT_COLORS[i % SUBJECT_COLORS.len()],
+                    "active": active,
+                })
+            })
+            .collect(),
+    );
+}
+
+/// Puts everything of a db.json from before classes existed into one class
+fn upgrade_single_cohort(doc: &mut Value) {
+    for list in ["students", "subjects", "absences"] {
+        if let Some(items) = doc.get_mut(list).and_then(Value::as_array_mut) {
+            for item in items {
+                item["cohort"] = Value::from(1);
+            }
+        }
+    }
+    doc["cohorts"] = json!([{ "id": 1, "name": "Klasse" }]);
+}
+
+/// Gives every entry that was saved without one a unique id
+fn upgrade_entry_ids(doc: &mut Value) {
+    let Some(entries) = doc.get_mut("data").and_then(Value::as_array_mut) else {
+        return;
+    };
+    let mut next = entries
+        .iter()
+        .filter_map(|e| e.get("id").and_then(Value::as_u64))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    for e in entries.iter_mut() {
+        if e.get("id").is_none() {
+            e["id"] = Value::from(next);
+            next += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{DataBase, ExcuseStatus, Reason};
+
+    fn upgrade(mut doc: Value) -> DataBase {
+        migrate(&mut doc).unwrap();
+        assert_eq!(version_of(&doc), SCHEMA_VERSION);
+        serde_json::from_value(doc).unwrap()
+    }
+
+    #[test]
+    fn v1_enum_names_become_records() {
+        let doc = json!({
+            "data": [
+                { "person": "Jan", "lesson": "Mathe", "lesson_time": "08:40:00",
+                  "delay_min": 5, "first_lesson": false, "date": "2024-03-04" },
+                { "person": "Zoe", "lesson": "EFSport", "lesson_time": "07:45:00",
+                  "delay_min": 2, "first_lesson": true, "date": "2024-03-05" },
+            ],
+            "absences": [
+                { "present": ["Nicole", "Jan"], "lesson": "EFChemie",
+                  "lesson_time": "07:45:00", "first_lesson": true, "date": "2024-03-05" },
+            ],
+        });
+        assert_eq!(version_of(&doc), 1);
+        let db = upgrade(doc);
+        let id = |name: &str| {
+            db.students
+                .iter()
+                .find(|s| s.first_name == name)
+                .unwrap()
+                .id
+        };
+
+        assert_eq!(db.cohorts.len(), 1);
+        // Pupils that were only ever saved by name are kept as well
+        assert_eq!(db.students.len(), LEGACY_ROSTER.len() + 1);
+        assert_eq!(db.subjects.len(), LEGACY_SUBJECTS.len());
+        assert!(db.students.iter().all(|s| s.cohort == 1));
+
+        assert_eq!(db.data.iter().map(|e| e.id).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(db.data[0].person, id("Jan"));
+        assert_eq!(db.data[1].person, id("Zoe"));
+        assert_eq!(db.subject_name(db.data[0].lesson), "Mathe");
+        assert_eq!(db.subject_name(db.data[1].lesson), "EFSport");
+        assert!(db
+            .data
+            .iter()
+            .all(|e| e.reason == Reason::Unknown && !e.excused));
+
+        let a = &db.absences[0];
+        assert_eq!((a.id, a.cohort), (1, 1));
+        assert_eq!(a.present, [id("Nicole"), id("Jan")]);
+        assert_eq!(db.subject_name(a.lesson), "EFChemie");
+        // Everyone else of the class was missing
+        assert_eq!(a.records.len(), db.students.len() - 2);
+        assert!(a.record(id("Jan")).is_none());
+        assert!(a.record(id("Zoe")).is_some());
+    }
+
+    #[test]
+    fn v2_absences_get_ids_and_entries_a_reason() {
+        let doc = json!({
+            "schema_version": 2,
+            "cohorts": [{ "id": 1, "name": "3a" }],
+            "students": [
+                { "id": 1, "cohort": 1, "first_name": "Anna", "last_name": "Meier", "alias": null },
+                { "id": 2, "cohort": 1, "first_name": "Ben", "last_name": "Frei", "alias": "Benni" },
+            ],
+            "subjects": [
+                { "id": 1, "cohort": 1, "name": "Mathe", "code": "M",
+                  "color": [76, 139, 245], "active": true },
+            ],
+            "data": [
+                { "id": 7, "person": 2, "lesson": 1, "lesson_time": "08:40:00",
+                  "delay_min": 3, "first_lesson": false, "date": "2024-03-04" },
+            ],
+            "absences": [
+                { "cohort": 1, "present": [1], "lesson": 1, "lesson_time": "07:45:00",
+                  "first_lesson": true, "date": "2024-03-04" },
+                { "cohort": 1, "present": [1, 2], "lesson": 1, "lesson_time": "08:40:00",
+                  "first_lesson": false, "date": "2024-03-04" },
+            ],
+        });
+        let db = upgrade(doc);
+
+        assert_eq!(db.students.len(), 2);
+        assert_eq!(db.student_name(2), "Benni");
+        assert_eq!(db.data[0].id, 7);
+        assert_eq!(db.data[0].reason, Reason::Unknown);
+        assert_eq!(db.data[0].note, "");
+        assert!(!db.data[0].excused);
+        assert_eq!(db.absences.iter().map(|a| a.id).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(db.absences[0].absentees(), [2]);
+        assert_eq!(db.absences[0].records[0].status, ExcuseStatus::Excused);
+        assert_eq!(
+            db.absences[0].records[0].deadline,
+            NaiveDate::from_ymd_opt(2024, 3, 18).unwrap()
+        );
+        assert!(db.absences[1].absentees().is_empty());
+    }
+
+    #[test]
+    fn recent_absences_stay_open() {
+        let today = time::get_today();
+        let doc = json!({
+            "schema_version": 5,
+            "cohorts": [{ "id": 1, "name": "3a" }],
+            "students": [
+                { "id": 1, "cohort": 1, "first_name": "Anna", "last_name": "Meier", "alias": null },
+            ],
+            "absences": [
+                { "id": 1, "cohort": 1, "present": [], "lesson": 1, "lesson_time": "07:45:00",
+                  "first_lesson": true, "date": today },
+            ],
+            "subjects": [],
+            "data": [],
+        });
+        let db = upgrade(doc);
+
+        let record = &db.absences[0].records[0];
+        assert_eq!(record.status, ExcuseStatus::Open);
+        assert!(record.deadline > today);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let mut doc = json!({ "schema_version": SCHEMA_VERSION + 1 });
+        assert!(matches!(
+            migrate(&mut doc),
+            Err(DataBaseError::UnsupportedVersion(v)) if v == SCHEMA_VERSION + 1
+        ));
+    }
+}
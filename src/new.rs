@@ -1,5 +1,5 @@
 use crate::bootstrap::*;
-use crate::db::Class;
+use crate::db::Reason;
 use crate::themes::*;
 use crate::ICON_FONT;
 use crate::{App, Message, ViewControl};
@@ -14,11 +14,22 @@ use serde_derive::{Deserialize, Serialize};
 
 pub fn new_entry_view(app: &App) -> Element<Message> {
     let s = "Erste Lektion".to_string();
+    let date = app.add_entry.date;
+    let day_off = match app.settings.calendar.day_off(date) {
+        Some(off) => format!("Schulfrei: {}", off.name),
+        None if !app.settings.calendar.is_school_day(date) => "Kein Schultag".to_string(),
+        None => String::new(),
+    };
     row![
         horizontal_space(),
         column![
             vertical_space(),
-            text("Neue Verspätung").size(30).style(text_fg_sec),
+            text(match app.editing {
+                Some(_) => "Verspätung bearbeiten",
+                None => "Neue Verspätung",
+            })
+            .size(30)
+            .style(text_fg_sec),
             horizontal_rule(1),
             combo_box(
                 &app.combo,
@@ -95,13 +106,34 @@ pub fn new_entry_view(app: &App) -> Element<Message> {
             ]
             .spacing(1)
             .align_y(Alignment::Center),
+            text(day_off).style(text_fg_danger),
             toggler(app.add_entry.first_lesson)
                 .on_toggle(Message::IsFirst)
                 .label(s),
+            row![
+                text("Grund").size(20),
+                pick_list(
+                    Reason::all(),
+                    Some(app.add_entry.reason),
+                    Message::EntryReason
+                ),
+                toggler(app.add_entry.excused)
+                    .on_toggle(Message::Excused)
+                    .label("Entschuldigt"),
+            ]
+            .spacing(10)
+            .padding(5)
+            .align_y(Alignment::Center),
+            text_input("Notiz", &app.add_entry.note)
+                .on_input(Message::EntryNote)
+                .padding(5),
             row![
                 styled_menu_button(
                     Bootstrap::CheckSquareFill,
-                    "Hinzufügen",
+                    match app.editing {
+                        Some(_) => "Speichern",
+                        None => "Hinzufügen",
+                    },
                     Message::AddEntry,
                     ColorType::Succes
                 ),
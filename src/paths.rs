@@ -0,0 +1,121 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Marker file next to the executable that keeps the data beside it
+pub const PORTABLE_MARKER: &str = "portable.txt";
+const APP_DIR: &str = "zuspaet";
+
+/// Files and folders that used to live in the working directory, next to
+/// the manual backups named `<time>-bak.json`
+const LEGACY_FILES: [&str; 7] = [
+    "db.json",
+    "db.json.prev",
+    "db.json.journal",
+    "db.sqlite",
+    "db.sqlite.journal",
+    "settings.json",
+    "backups",
+];
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Where the data directory comes from, shown in the settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Location {
+    Argument,
+    Portable,
+    User,
+    WorkingDir,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Location::Argument => write!(f, "--data-dir"),
+            Location::Portable => write!(f, "portabel"),
+            Location::User => write!(f, "Benutzerordner"),
+            Location::WorkingDir => write!(f, "Arbeitsverzeichnis"),
+        }
+    }
+}
+
+static LOCATION: OnceLock<Location> = OnceLock::new();
+
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+/// `--data-dir` wins, then the portable marker, then the platform data
+/// directory. Only if there is none the working directory is used as before.
+fn resolve(arg: Option<PathBuf>) -> (PathBuf, Location) {
+    if let Some(dir) = arg {
+        return (dir, Location::Argument);
+    }
+    if let Some(dir) = exe_dir().filter(|d| d.join(PORTABLE_MARKER).exists()) {
+        return (dir, Location::Portable);
+    }
+    match dirs::data_dir() {
+        Some(dir) => (dir.join(APP_DIR), Location::User),
+        None => (PathBuf::from("."), Location::WorkingDir),
+    }
+}
+
+/// Decides on the data directory, creates it and moves the files of older
+/// versions out of the working directory. Must run before anything is loaded.
+pub fn init(arg: Option<PathBuf>) -> io::Result<Vec<String>> {
+    let (dir, location) = resolve(arg);
+    std::fs::create_dir_all(&dir)?;
+    let moved = migrate_from(&std::env::current_dir()?, &dir)?;
+    let _ = DATA_DIR.set(dir);
+    let _ = LOCATION.set(location);
+    Ok(moved)
+}
+
+/// Moves the old files over, but only into a directory without a database,
+/// so nothing that is already there gets replaced
+fn migrate_from(old: &Path, new: &Path) -> io::Result<Vec<String>> {
+    let same = match (old.canonicalize(), new.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    };
+    let has_db = ["db.json", "db.sqlite"].iter().any(|f| new.join(f).exists());
+    if same || has_db {
+        return Ok(vec![]);
+    }
+    let backups = std::fs::read_dir(old)?
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with("-bak.json"));
+    let names: Vec<String> = LEGACY_FILES.iter().map(|n| n.to_string()).chain(backups).collect();
+    let mut moved = vec![];
+    for name in names {
+        let from = old.join(&name);
+        let to = new.join(&name);
+        if !from.exists() || to.exists() {
+            continue;
+        }
+        // Renaming fails across drives, copy the plain files there instead
+        if std::fs::rename(&from, &to).is_err() {
+            if from.is_dir() {
+                continue;
+            }
+            std::fs::copy(&from, &to)?;
+            std::fs::remove_file(&from)?;
+        }
+        moved.push(name);
+    }
+    Ok(moved)
+}
+
+pub fn data_dir() -> &'static Path {
+    DATA_DIR.get_or_init(|| PathBuf::from("."))
+}
+
+pub fn location() -> Location {
+    *LOCATION.get().unwrap_or(&Location::WorkingDir)
+}
+
+/// Path of a file in the data directory
+pub fn data_file(name: &str) -> String {
+    data_dir().join(name).to_string_lossy().to_string()
+}
@@ -0,0 +1,455 @@
+use crate::bootstrap::*;
+use crate::db::{
+    self, Change, DataBase, DataBaseError, Entry, LessonAbs, Reason, Student, StudentId,
+};
+use crate::themes::{self, styled_button, ColorType};
+use crate::time;
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use chrono::{Datelike, Months, NaiveDate};
+use iced::widget::{column, pick_list, row, text, toggler};
+use iced::{Alignment, Element, Task};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::io;
+use std::path::Path;
+
+/// Month the school year starts in
+const SCHOOL_YEAR_START: u32 = 8;
+
+/// Which records count as old
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cutoff {
+    #[default]
+    Off,
+    Months(u32),
+    /// Everything before the current school year and the given number of
+    /// closed ones before it
+    SchoolYears(u32),
+}
+
+impl Cutoff {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Cutoff::Off,
+            Cutoff::Months(6),
+            Cutoff::Months(12),
+            Cutoff::Months(24),
+            Cutoff::SchoolYears(0),
+            Cutoff::SchoolYears(1),
+            Cutoff::SchoolYears(2),
+        ]
+    }
+
+    /// Records dated before the returned day are old
+    pub fn date(self, today: NaiveDate) -> Option<NaiveDate> {
+        match self {
+            Cutoff::Off => None,
+            Cutoff::Months(n) => today.checked_sub_months(Months::new(n)),
+            Cutoff::SchoolYears(n) => {
+                let year = match today.month() >= SCHOOL_YEAR_START {
+                    true => today.year(),
+                    false => today.year() - 1,
+                };
+                NaiveDate::from_ymd_opt(year - n as i32, SCHOOL_YEAR_START, 1)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Cutoff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Cutoff::Off => write!(f, "Nie"),
+            Cutoff::Months(n) => write!(f, "Älter als {} Monate", n),
+            Cutoff::SchoolYears(0) => write!(f, "Vor dem laufenden Schuljahr"),
+            Cutoff::SchoolYears(1) => write!(f, "Vor dem letzten Schuljahr"),
+            Cutoff::SchoolYears(n) => write!(f, "Vor den letzten {} Schuljahren", n),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Action {
+    #[default]
+    Delete,
+    /// Keeps the records for the statistics but without the names
+    Pseudonymise,
+}
+
+impl Action {
+    pub fn all() -> Vec<Self> {
+        vec![Action::Delete, Action::Pseudonymise]
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Action::Delete => write!(f, "löschen"),
+            Action::Pseudonymise => write!(f, "pseudonymisieren"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Policy {
+    pub cutoff: Cutoff,
+    pub action: Action,
+    /// Apply the policy every time the database is loaded
+    pub automatic: bool,
+}
+
+/// What applying a policy changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preview {
+    pub cutoff: NaiveDate,
+    pub entries: usize,
+    pub absences: usize,
+    pub students: usize,
+}
+
+impl Preview {
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0 && self.absences == 0
+    }
+}
+
+/// An opaque name that can't be traced back to the student. Every run
+/// draws new ones, so pseudonyms of different runs can't be linked either.
+fn token(hasher: &RandomState, id: StudentId) -> String {
+    format!("P-{:012x}", hasher.hash_one(id) & 0xffff_ffff_ffff)
+}
+
+/// The changes that apply `policy` to `db`. Pseudonymised entries move to a
+/// stand-in student per real student and cohort, so counts and sums per
+/// class, subject and lesson stay the same, their note and reason are
+/// dropped. Archived absences only keep how many were missing.
+pub fn apply(db: &DataBase, policy: Policy, today: NaiveDate) -> Option<(Vec<Change>, Preview)> {
+    let cutoff = policy.cutoff.date(today)?;
+    let is_pseudonym = |id: StudentId| db.student(id).is_some_and(|s| s.pseudonym);
+    let mut changes = vec![];
+    let mut preview = Preview {
+        cutoff,
+        entries: 0,
+        absences: 0,
+        students: 0,
+    };
+    let mut affected: HashSet<StudentId> = HashSet::new();
+
+    match policy.action {
+        Action::Delete => {
+            for e in db.data.iter().filter(|e| e.date < cutoff) {
+                preview.entries += 1;
+                affected.insert(e.person);
+                changes.push(Change::DeleteEntry(e.id));
+            }
+            for a in db.absences.iter().filter(|a| a.date < cutoff) {
+                preview.absences += 1;
+                changes.push(Change::DeleteAbsence(a.id));
+            }
+            // Stand-ins nothing refers to any more go as well
+            let used: HashSet<StudentId> = db
+                .data
+                .iter()
+                .filter(|e| e.date >= cutoff)
+                .map(|e| e.person)
+                .collect();
+            let kept = |s: &&Student| !s.pseudonym || used.contains(&s.id);
+            if !db.students.iter().all(|s| kept(&s)) {
+                changes.push(Change::SetStudents(
+                    db.students.iter().filter(kept).cloned().collect(),
+                ));
+            }
+        }
+        Action::Pseudonymise => {
+            let hasher = RandomState::new();
+            let mut students = db.students.clone();
+            let mut next = db.next_student_id();
+            let mut stand_ins: HashMap<StudentId, StudentId> = HashMap::new();
+            let mut entries = vec![];
+            for e in db.data.iter().filter(|e| e.date < cutoff) {
+                // A note can name someone just as well, the reason tells too much
+                let details = !e.note.is_empty() || e.reason != Reason::Unknown;
+                let mut e = Entry {
+                    note: String::new(),
+                    reason: Reason::Unknown,
+                    ..e.clone()
+                };
+                match db.student(e.person).filter(|s| !s.pseudonym) {
+                    Some(student) => {
+                        e.person = *stand_ins.entry(student.id).or_insert_with(|| {
+                            students.push(Student {
+                                id: next,
+                                cohort: student.cohort,
+                                first_name: token(&hasher, student.id),
+                                last_name: String::new(),
+                                alias: None,
+                                pseudonym: true,
+                            });
+                            next += 1;
+                            next - 1
+                        });
+                        affected.insert(student.id);
+                    }
+                    None if !details => continue,
+                    None => (),
+                }
+                preview.entries += 1;
+                entries.push(Change::UpdateEntry(e));
+            }
+            // The stand-ins have to exist before the entries point to them
+            if students.len() > db.students.len() {
+                changes.push(Change::SetStudents(students));
+            }
+            changes.extend(entries);
+            for a in db
+                .absences
+                .iter()
+                .filter(|a| a.date < cutoff && a.archived_absent.is_none())
+            {
+                affected.extend(a.present.iter().copied().chain(a.absentees()));
+                changes.push(Change::UpdateAbsence(LessonAbs {
+                    archived_absent: Some(a.absent_count() as u32),
+                    present: vec![],
+                    records: vec![],
+                    ..a.clone()
+                }));
+                preview.absences += 1;
+            }
+        }
+    }
+    preview.students = affected.iter().filter(|id| !is_pseudonym(**id)).count();
+    Some((changes, preview))
+}
+
+/// Runs the policy from the settings. The old records must not survive
+/// anywhere the program keeps copies, so this can't be undone: the undo
+/// history is dropped and the database saved right away, which clears the
+/// journal, and the older copies next to it are removed. `None` if nothing
+/// was old enough.
+fn run(app: &mut App, automatic: bool) -> Option<Task<Message>> {
+    let policy = app.settings.retention;
+    let (changes, preview) = apply(&app.db, policy, time::get_today())?;
+    if preview.is_empty() {
+        if !automatic {
+            app.notify(Toast::new(
+                "Aufbewahrung",
+                "Keine Daten vor dem Stichtag",
+                Status::Secondary,
+            ));
+        }
+        return None;
+    }
+    app.write(Change::Batch(changes));
+    app.history.clear();
+    app.fix_selection();
+    app.notify(Toast::new(
+        "Aufbewahrung",
+        &format!(
+            "{} Verspätungen und {} Absenzen vor dem {} {}",
+            preview.entries,
+            preview.absences,
+            preview.cutoff.format("%d.%m.%Y"),
+            match policy.action {
+                Action::Delete => "gelöscht",
+                Action::Pseudonymise => "pseudonymisiert",
+            }
+        ),
+        Status::Success,
+    ));
+    if app.db_locked {
+        return None;
+    }
+    app.saving = true;
+    let revision = app.revision;
+    let storage = app.storage.clone();
+    let db = app.db.clone();
+    let sets = app.current_settings();
+    Some(Task::perform(
+        async move {
+            crate::save_all(storage.clone(), Some(db), sets).await?;
+            remove_copies(storage.path())
+        },
+        move |result| Message::Saved {
+            revision,
+            manual: false,
+            result,
+        },
+    ))
+}
+
+/// Deletes the copy the last save kept of the database at `path` and the
+/// ones made before migrations
+fn remove_copies(path: &str) -> Result<(), DataBaseError> {
+    let remove = |file: &Path| match std::fs::remove_file(file) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => {
+            Err(DataBaseError::from(e).at(&file.to_string_lossy()))
+        }
+        _ => Ok(()),
+    };
+    remove(Path::new(&db::prev_path(path)))?;
+    let file = Path::new(path);
+    let Some(name) = file.file_name().map(|n| n.to_string_lossy().to_string()) else {
+        return Ok(());
+    };
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let migrated = format!("{}.v", name);
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let entry_name = entry.file_name().to_string_lossy().to_string();
+        if entry_name.starts_with(&migrated) && entry_name.ends_with(".bak") {
+            remove(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Called after loading, applies the policy if it is set to run on its own.
+/// The returned task saves the result.
+pub fn apply_on_load(app: &mut App) -> Option<Task<Message>> {
+    match app.settings.retention.automatic {
+        true => run(app, true),
+        false => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RetentionState {
+    confirm: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum RetentionMsg {
+    Cutoff(Cutoff),
+    Action(Action),
+    Automatic(bool),
+    Apply,
+    Confirm,
+    Cancel,
+}
+
+pub fn update_retention(app: &mut App, msg: RetentionMsg) -> Task<Message> {
+    match msg {
+        RetentionMsg::Cutoff(c) => {
+            app.settings.retention.cutoff = c;
+            app.retention.confirm = false;
+            app.mark_changed();
+        }
+        RetentionMsg::Action(a) => {
+            app.settings.retention.action = a;
+            app.retention.confirm = false;
+            app.mark_changed();
+        }
+        RetentionMsg::Automatic(b) => {
+            app.settings.retention.automatic = b;
+            app.mark_changed();
+        }
+        RetentionMsg::Apply => app.retention.confirm = true,
+        RetentionMsg::Cancel => app.retention.confirm = false,
+        RetentionMsg::Confirm => {
+            app.retention.confirm = false;
+            if let Some(task) = run(app, false) {
+                return task;
+            }
+        }
+    }
+    Task::none()
+}
+
+pub fn retention_view(app: &App) -> Element<Message> {
+    let policy = app.settings.retention;
+    let preview = apply(&app.db, policy, time::get_today()).map(|(_, p)| p);
+
+    let summary = match preview {
+        None => text("Es werden keine Daten automatisch entfernt").style(themes::text_fg_sec),
+        Some(p) if p.is_empty() => text(format!(
+            "Stichtag {}: keine älteren Daten",
+            p.cutoff.format("%d.%m.%Y")
+        ))
+        .style(themes::text_fg_sec),
+        Some(p) => text(format!(
+            "Stichtag {}: {} Verspätungen und {} Absenzen von {} Personen werden {}",
+            p.cutoff.format("%d.%m.%Y"),
+            p.entries,
+            p.absences,
+            p.students,
+            policy.action
+        ))
+        .style(themes::text_fg_danger),
+    };
+
+    let backups: Vec<String> = app
+        .backup
+        .list()
+        .iter()
+        .map(|b| b.created.format("%d.%m.%Y %H:%M").to_string())
+        .collect();
+    let kept_in = match backups.is_empty() {
+        true => "Es gibt keine Backups mit den alten Daten.".to_string(),
+        false => format!(
+            "Diese Backups behalten die alten Daten, bis sie aufgeräumt werden: {}",
+            backups.join(", ")
+        ),
+    };
+
+    let actions = match (
+        preview.is_some_and(|p| !p.is_empty()),
+        app.retention.confirm,
+    ) {
+        (true, true) => row![
+            text("Wirklich anwenden?").style(themes::text_fg_danger),
+            styled_button(
+                Bootstrap::TrashthreeFill,
+                "Ja, anwenden",
+                Message::Retention(RetentionMsg::Confirm),
+                ColorType::Danger,
+            ),
+            styled_button(
+                Bootstrap::XSquareFill,
+                "Abbrechen",
+                Message::Retention(RetentionMsg::Cancel),
+                ColorType::Secondary,
+            ),
+        ],
+        (true, false) => row![styled_button(
+            Bootstrap::TrashthreeFill,
+            "Jetzt anwenden",
+            Message::Retention(RetentionMsg::Apply),
+            ColorType::Primary,
+        )],
+        (false, _) => row![],
+    };
+
+    column![
+        text("Aufbewahrung").size(22).style(themes::text_fg),
+        row![
+            text("Daten"),
+            pick_list(Cutoff::all(), Some(policy.cutoff), |c| {
+                Message::Retention(RetentionMsg::Cutoff(c))
+            }),
+            pick_list(Action::all(), Some(policy.action), |a| {
+                Message::Retention(RetentionMsg::Action(a))
+            }),
+            toggler(policy.automatic)
+                .label("Beim Laden automatisch")
+                .on_toggle(|b| Message::Retention(RetentionMsg::Automatic(b))),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center),
+        summary,
+        text(
+            "Das kann nicht rückgängig gemacht werden. Die Datenbank wird sofort gespeichert, \
+             ältere Kopien daneben werden gelöscht."
+        )
+        .style(themes::text_fg_sec),
+        text(kept_in).style(themes::text_fg_sec),
+        actions.spacing(5).align_y(Alignment::Center),
+    ]
+    .spacing(5)
+    .into()
+}
@@ -0,0 +1,229 @@
+use crate::bootstrap::*;
+use crate::db::{Change, Cohort, Student, StudentId};
+use crate::themes::{self, styled_button, ColorType};
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use iced::widget::{button, column, horizontal_space, pick_list, row, text, text_input};
+use iced::{Alignment, Element, Task};
+
+#[derive(Debug, Clone, Default)]
+pub struct RosterState {
+    first_name: String,
+    last_name: String,
+    alias: String,
+    editing: Option<StudentId>,
+    cohort_name: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum RosterMsg {
+    FirstName(String),
+    LastName(String),
+    Alias(String),
+    Save,
+    Edit(StudentId),
+    Cancel,
+    Remove(StudentId),
+    CohortName(String),
+    AddCohort,
+    RenameCohort,
+}
+
+pub fn update_roster(app: &mut App, msg: RosterMsg) -> Task<Message> {
+    match msg {
+        RosterMsg::FirstName(s) => app.roster.first_name = s,
+        RosterMsg::LastName(s) => app.roster.last_name = s,
+        RosterMsg::Alias(s) => app.roster.alias = s,
+        RosterMsg::Save => {
+            let Some(cohort) = app.cohort else {
+                app.notify(Toast::new(
+                    "Fehler",
+                    "Zuerst eine Klasse anlegen",
+                    Status::Danger,
+                ));
+                return Task::none();
+            };
+            let first_name = app.roster.first_name.trim().to_string();
+            if first_name.is_empty() {
+                app.notify(Toast::new("Fehler", "Vorname fehlt", Status::Danger));
+                return Task::none();
+            }
+            let alias = match app.roster.alias.trim() {
+                "" => None,
+                a => Some(a.to_string()),
+            };
+            let student = Student {
+                id: app.roster.editing.unwrap_or(app.db.next_student_id()),
+                cohort: match app.roster.editing.and_then(|id| app.db.student(id)) {
+                    Some(s) => s.cohort,
+                    None => cohort,
+                },
+                first_name,
+                last_name: app.roster.last_name.trim().to_string(),
+                alias,
+                pseudonym: false,
+            };
+            let mut students = app.db.students.clone();
+            match students.iter_mut().find(|s| s.id == student.id) {
+                Some(s) => *s = student,
+                None => students.push(student),
+            }
+            app.commit(Change::SetStudents(students));
+            app.roster = RosterState::default();
+            app.refresh_pickers();
+        }
+        RosterMsg::Edit(id) => {
+            if let Some(s) = app.db.student(id) {
+                app.roster = RosterState {
+                    first_name: s.first_name.clone(),
+                    last_name: s.last_name.clone(),
+                    alias: s.alias.clone().unwrap_or_default(),
+                    editing: Some(id),
+                    cohort_name: app.roster.cohort_name.clone(),
+                };
+            }
+        }
+        RosterMsg::Cancel => app.roster = RosterState::default(),
+        RosterMsg::Remove(id) => {
+            if app.db.student_in_use(id) {
+                app.notify(Toast::new(
+                    "Nicht entfernt",
+                    "Für diese Person gibt es noch Einträge",
+                    Status::Danger,
+                ));
+            } else {
+                let name = app.db.student_name(id);
+                let mut students = app.db.students.clone();
+                students.retain(|s| s.id != id);
+                app.commit_undoable(Change::SetStudents(students), "Person entfernt", &name);
+                app.refresh_pickers();
+            }
+        }
+        RosterMsg::CohortName(s) => app.roster.cohort_name = s,
+        RosterMsg::AddCohort => {
+            let name = app.roster.cohort_name.trim().to_string();
+            if name.is_empty() {
+                return Task::none();
+            }
+            let cohort = Cohort {
+                id: app.db.next_cohort_id(),
+                name,
+            };
+            app.roster.cohort_name.clear();
+            let mut cohorts = app.db.cohorts.clone();
+            cohorts.push(cohort.clone());
+            app.commit(Change::SetCohorts(cohorts));
+            return Task::done(Message::SelectCohort(cohort));
+        }
+        RosterMsg::RenameCohort => {
+            let name = app.roster.cohort_name.trim().to_string();
+            if name.is_empty() {
+                return Task::none();
+            }
+            let mut cohorts = app.db.cohorts.clone();
+            if let Some(c) = cohorts.iter_mut().find(|c| Some(c.id) == app.cohort) {
+                c.name = name;
+                app.roster.cohort_name.clear();
+                app.commit(Change::SetCohorts(cohorts));
+            }
+        }
+    }
+    Task::none()
+}
+
+pub fn roster_view(app: &App) -> Element<Message> {
+    let mut students = column![].spacing(2);
+    for s in app.db.roster(app.cohort) {
+        students = students.push(
+            row![
+                text(s.to_string()).size(18).style(themes::text_fg_succes),
+                text(s.full_name()).style(themes::text_fg_sec),
+                horizontal_space(),
+                button(
+                    text(icon_to_string(Bootstrap::PencilFill))
+                        .font(ICON_FONT)
+                        .style(themes::text_fg)
+                )
+                .on_press(Message::Roster(RosterMsg::Edit(s.id)))
+                .style(button::text),
+                button(
+                    text(icon_to_string(Bootstrap::TrashthreeFill))
+                        .font(ICON_FONT)
+                        .style(themes::text_fg_danger)
+                )
+                .on_press(Message::Roster(RosterMsg::Remove(s.id)))
+                .style(button::text),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+    }
+
+    let mut form = row![
+        text_input("Vorname", &app.roster.first_name)
+            .on_input(|s| Message::Roster(RosterMsg::FirstName(s))),
+        text_input("Nachname", &app.roster.last_name)
+            .on_input(|s| Message::Roster(RosterMsg::LastName(s))),
+        text_input("Rufname (optional)", &app.roster.alias)
+            .on_input(|s| Message::Roster(RosterMsg::Alias(s)))
+            .on_submit(Message::Roster(RosterMsg::Save)),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+    form = match app.roster.editing {
+        Some(_) => form
+            .push(styled_button(
+                Bootstrap::PersonFillCheck,
+                "Speichern",
+                Message::Roster(RosterMsg::Save),
+                ColorType::Succes,
+            ))
+            .push(styled_button(
+                Bootstrap::XSquareFill,
+                "Abbrechen",
+                Message::Roster(RosterMsg::Cancel),
+                ColorType::Secondary,
+            )),
+        None => form.push(styled_button(
+            Bootstrap::PersonPlusFill,
+            "Hinzufügen",
+            Message::Roster(RosterMsg::Save),
+            ColorType::Succes,
+        )),
+    };
+
+    let cohorts = row![
+        text("Klasse:"),
+        pick_list(
+            app.db.cohorts.clone(),
+            app.cohort.and_then(|c| app.db.cohort(c).cloned()),
+            Message::SelectCohort
+        ),
+        text_input("Name der Klasse", &app.roster.cohort_name)
+            .on_input(|s| Message::Roster(RosterMsg::CohortName(s))),
+        styled_button(
+            Bootstrap::PlusSquareFill,
+            "Neue Klasse",
+            Message::Roster(RosterMsg::AddCohort),
+            ColorType::Succes,
+        ),
+        styled_button(
+            Bootstrap::PencilFill,
+            "Umbenennen",
+            Message::Roster(RosterMsg::RenameCohort),
+            ColorType::Secondary,
+        ),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+
+    column![
+        text("Klassen").size(22).style(themes::text_fg),
+        cohorts,
+        text("Schüler").size(22).style(themes::text_fg),
+        form,
+        students,
+    ]
+    .spacing(5)
+    .into()
+}
@@ -0,0 +1,502 @@
+use crate::bootstrap::*;
+use crate::themes::{self, styled_button, ColorType};
+use crate::time;
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeDelta, Weekday};
+use iced::widget::{button, column, pick_list, row, text, text_input};
+use iced::{Alignment, Element, Task};
+use serde_derive::{Deserialize, Serialize};
+
+/// School days of the week
+pub const WEEKDAYS: [Weekday; 5] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+];
+
+pub fn weekday_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Mo",
+        Weekday::Tue => "Di",
+        Weekday::Wed => "Mi",
+        Weekday::Thu => "Do",
+        Weekday::Fri => "Fr",
+        Weekday::Sat => "Sa",
+        Weekday::Sun => "So",
+    }
+}
+
+fn all_weekdays() -> Vec<Weekday> {
+    WEEKDAYS.to_vec()
+}
+
+/// One lesson of the bell schedule
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Period {
+    pub start: NaiveTime,
+    pub minutes: u32,
+    /// Weekdays the lesson is held on. A short day leaves it out of the
+    /// afternoon lessons, a late start out of the morning ones.
+    #[serde(default = "all_weekdays")]
+    pub days: Vec<Weekday>,
+}
+
+impl Period {
+    pub fn end(&self) -> NaiveTime {
+        self.start + TimeDelta::minutes(self.minutes as i64)
+    }
+}
+
+/// Lesson times that apply from `valid_from` until the next schedule starts
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BellSchedule {
+    pub valid_from: NaiveDate,
+    pub periods: Vec<Period>,
+}
+
+impl Default for BellSchedule {
+    /// The times that used to be built into the program
+    fn default() -> Self {
+        let starts = [
+            (7, 45),
+            (8, 40),
+            (9, 35),
+            (10, 35),
+            (11, 30),
+            (12, 25),
+            (13, 20),
+            (14, 15),
+            (15, 10),
+            (16, 5),
+        ];
+        BellSchedule {
+            valid_from: NaiveDate::from_ymd_opt(2000, 1, 1).expect("Couldnt create date"),
+            periods: starts
+                .into_iter()
+                .map(|(h, m)| Period {
+                    start: NaiveTime::from_hms_opt(h, m, 0).expect("Couldnt create time"),
+                    minutes: 45,
+                    days: all_weekdays(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl BellSchedule {
+    /// Start of every lesson, whatever day it is held on. Slot numbers
+    /// index this list.
+    pub fn starts(&self) -> Vec<NaiveTime> {
+        self.periods.iter().map(|p| p.start).collect()
+    }
+
+    /// Start of the lessons held on the weekday of `date`
+    pub fn starts_on(&self, date: NaiveDate) -> Vec<NaiveTime> {
+        self.periods
+            .iter()
+            .filter(|p| p.days.contains(&date.weekday()))
+            .map(|p| p.start)
+            .collect()
+    }
+
+    /// Whether lesson `slot` is held on `weekday`
+    pub fn is_held(&self, slot: usize, weekday: Weekday) -> bool {
+        self.periods
+            .get(slot)
+            .is_some_and(|p| p.days.contains(&weekday))
+    }
+
+    /// The next lesson after `i` held on one of its days
+    fn next_on_same_day(&self, i: usize) -> Option<&Period> {
+        let p = self.periods.get(i)?;
+        self.periods[i + 1..]
+            .iter()
+            .find(|n| n.days.iter().any(|d| p.days.contains(d)))
+    }
+
+    /// Minutes between the end of lesson `i` and the start of the next one
+    pub fn break_after(&self, i: usize) -> Option<i64> {
+        let next = self.next_on_same_day(i)?;
+        Some((next.start - self.periods[i].end()).num_minutes())
+    }
+
+    /// Lessons must not be empty, overlap or come out of order
+    fn validate(&self) -> Result<(), String> {
+        if self.periods.is_empty() {
+            return Err("Mindestens eine Lektion".to_string());
+        }
+        for (i, p) in self.periods.iter().enumerate() {
+            if p.minutes == 0 {
+                return Err(format!("{}. Lektion hat keine Dauer", i + 1));
+            }
+            if p.days.is_empty() {
+                return Err(format!("{}. Lektion findet an keinem Tag statt", i + 1));
+            }
+            // `end` would wrap around to the early morning
+            let length = TimeDelta::minutes(p.minutes as i64);
+            let (_, wrapped) = p.start.overflowing_add_signed(length);
+            if wrapped != 0 {
+                return Err(format!("{}. Lektion endet nach Mitternacht", i + 1));
+            }
+            if self.periods.get(i + 1).is_some_and(|n| n.start < p.start) {
+                return Err(format!("{}. Lektion beginnt vor der vorherigen", i + 2));
+            }
+            if self.break_after(i).is_some_and(|b| b < 0) {
+                return Err(format!("{}. Lektion überschneidet die nächste", i + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// All bell schedules, sorted by the day they start
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Schedules(pub Vec<BellSchedule>);
+
+impl Default for Schedules {
+    fn default() -> Self {
+        Schedules(vec![BellSchedule::default()])
+    }
+}
+
+impl Schedules {
+    /// The schedule that applied on `date`. Dates before the first one use
+    /// the first, so old entries always resolve to something.
+    pub fn for_date(&self, date: NaiveDate) -> &BellSchedule {
+        self.0
+            .iter()
+            .rev()
+            .find(|s| s.valid_from <= date)
+            .or(self.0.first())
+            .unwrap_or(default_schedule())
+    }
+
+    pub fn today(&self) -> &BellSchedule {
+        self.for_date(time::get_today())
+    }
+
+    fn sort(&mut self) {
+        self.0.sort_by_key(|s| s.valid_from);
+    }
+}
+
+fn default_schedule() -> &'static BellSchedule {
+    static DEFAULT: std::sync::OnceLock<BellSchedule> = std::sync::OnceLock::new();
+    DEFAULT.get_or_init(BellSchedule::default)
+}
+
+/// Label of a schedule in the picker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduleChoice(pub usize, pub NaiveDate);
+
+impl std::fmt::Display for ScheduleChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Gültig ab {}", self.1.format("%d.%m.%Y"))
+    }
+}
+
+/// A lesson being edited, as typed
+#[derive(Debug, Clone)]
+struct DraftPeriod {
+    start: String,
+    minutes: String,
+    days: Vec<Weekday>,
+}
+
+/// The schedule being edited, as typed
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleState {
+    selected: usize,
+    valid_from: String,
+    periods: Vec<DraftPeriod>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ScheduleMsg {
+    Select(ScheduleChoice),
+    ValidFrom(String),
+    Start(usize, String),
+    Minutes(usize, String),
+    ToggleDay(usize, Weekday),
+    AddPeriod,
+    RemovePeriod(usize),
+    Apply,
+    New,
+    Delete,
+}
+
+/// Fills the form from the saved schedule
+pub fn load_draft(app: &mut App) {
+    let schedules = &app.settings.schedules.0;
+    let selected = app.schedule.selected.min(schedules.len().saturating_sub(1));
+    let Some(s) = schedules.get(selected) else {
+        return;
+    };
+    app.schedule = ScheduleState {
+        selected,
+        valid_from: s.valid_from.format("%d.%m.%Y").to_string(),
+        periods: s
+            .periods
+            .iter()
+            .map(|p| DraftPeriod {
+                start: p.start.format("%H:%M").to_string(),
+                minutes: p.minutes.to_string(),
+                days: p.days.clone(),
+            })
+            .collect(),
+    };
+}
+
+fn parse_draft(draft: &ScheduleState) -> Result<BellSchedule, String> {
+    let valid_from = NaiveDate::parse_from_str(draft.valid_from.trim(), "%d.%m.%Y")
+        .map_err(|_| format!("\"{}\" ist kein Datum (TT.MM.JJJJ)", draft.valid_from))?;
+    let mut periods = vec![];
+    for (i, p) in draft.periods.iter().enumerate() {
+        let start = NaiveTime::parse_from_str(p.start.trim(), "%H:%M")
+            .map_err(|_| format!("{}. Lektion: \"{}\" ist keine Uhrzeit", i + 1, p.start))?;
+        let minutes = p
+            .minutes
+            .trim()
+            .parse()
+            .map_err(|_| format!("{}. Lektion: \"{}\" sind keine Minuten", i + 1, p.minutes))?;
+        // Keep the days in week order whatever order they were clicked in
+        let days = WEEKDAYS
+            .into_iter()
+            .filter(|d| p.days.contains(d))
+            .collect();
+        periods.push(Period {
+            start,
+            minutes,
+            days,
+        });
+    }
+    let schedule = BellSchedule {
+        valid_from,
+        periods,
+    };
+    schedule.validate()?;
+    Ok(schedule)
+}
+
+pub fn update_schedule(app: &mut App, msg: ScheduleMsg) -> Task<Message> {
+    match msg {
+        ScheduleMsg::Select(c) => {
+            app.schedule.selected = c.0;
+            load_draft(app);
+        }
+        ScheduleMsg::ValidFrom(s) => app.schedule.valid_from = s,
+        ScheduleMsg::Start(i, s) => {
+            if let Some(p) = app.schedule.periods.get_mut(i) {
+                p.start = s;
+            }
+        }
+        ScheduleMsg::Minutes(i, s) => {
+            if let Some(p) = app.schedule.periods.get_mut(i) {
+                p.minutes = s;
+            }
+        }
+        ScheduleMsg::ToggleDay(i, day) => {
+            if let Some(p) = app.schedule.periods.get_mut(i) {
+                match p.days.contains(&day) {
+                    true => p.days.retain(|d| *d != day),
+                    false => p.days.push(day),
+                }
+            }
+        }
+        ScheduleMsg::AddPeriod => {
+            // Right after the last lesson with a five minute break
+            let start = app
+                .schedule
+                .periods
+                .last()
+                .and_then(|p| {
+                    let start = NaiveTime::parse_from_str(p.start.trim(), "%H:%M").ok()?;
+                    let minutes: i64 = p.minutes.trim().parse().ok()?;
+                    Some(start + TimeDelta::minutes(minutes + 5))
+                })
+                .unwrap_or(NaiveTime::from_hms_opt(8, 0, 0).expect("Couldnt create time"));
+            app.schedule.periods.push(DraftPeriod {
+                start: start.format("%H:%M").to_string(),
+                minutes: "45".to_string(),
+                days: all_weekdays(),
+            });
+        }
+        ScheduleMsg::RemovePeriod(i) => {
+            if i < app.schedule.periods.len() {
+                app.schedule.periods.remove(i);
+            }
+        }
+        ScheduleMsg::Apply => match parse_draft(&app.schedule) {
+            Ok(schedule)
+                if app.settings.schedules.0.iter().enumerate().any(|(i, s)| {
+                    i != app.schedule.selected && s.valid_from == schedule.valid_from
+                }) =>
+            {
+                app.notify(Toast::new(
+                    "Nicht übernommen",
+                    "Ab diesem Datum gilt schon ein anderer Stundenplan",
+                    Status::Danger,
+                ));
+            }
+            Ok(schedule) => {
+                let valid_from = schedule.valid_from;
+                let schedules = &mut app.settings.schedules;
+                match schedules.0.get_mut(app.schedule.selected) {
+                    Some(s) => *s = schedule,
+                    None => schedules.0.push(schedule),
+                }
+                schedules.sort();
+                app.schedule.selected = schedules
+                    .0
+                    .iter()
+                    .position(|s| s.valid_from == valid_from)
+                    .unwrap_or(0);
+                load_draft(app);
+                app.mark_changed();
+                app.notify(Toast::new(
+                    "Stundenplan",
+                    "Lektionszeiten übernommen",
+                    Status::Success,
+                ));
+            }
+            Err(e) => app.notify(Toast::new("Nicht übernommen", &e, Status::Danger)),
+        },
+        ScheduleMsg::New => {
+            // Starts as a copy of the current one, valid from today
+            let mut schedule = app.settings.schedules.today().clone();
+            schedule.valid_from = time::get_today();
+            let schedules = &mut app.settings.schedules;
+            schedules.0.retain(|s| s.valid_from != schedule.valid_from);
+            schedules.0.push(schedule);
+            schedules.sort();
+            app.schedule.selected = schedules
+                .0
+                .iter()
+                .position(|s| s.valid_from == time::get_today())
+                .unwrap_or(0);
+            load_draft(app);
+            app.mark_changed();
+        }
+        ScheduleMsg::Delete => {
+            if app.settings.schedules.0.len() > 1 {
+                app.settings.schedules.0.remove(app.schedule.selected);
+                app.schedule.selected = app.schedule.selected.saturating_sub(1);
+                load_draft(app);
+                app.mark_changed();
+            }
+        }
+    }
+    Task::none()
+}
+
+/// Bell schedule section of the settings
+pub fn schedule_view(app: &App) -> Element<Message> {
+    let choices: Vec<ScheduleChoice> = app
+        .settings
+        .schedules
+        .0
+        .iter()
+        .enumerate()
+        .map(|(i, s)| ScheduleChoice(i, s.valid_from))
+        .collect();
+    let selected = choices.get(app.schedule.selected).copied();
+    let saved = app.settings.schedules.0.get(app.schedule.selected);
+
+    let mut header = row![
+        pick_list(choices, selected, |c| Message::Schedule(
+            ScheduleMsg::Select(c)
+        )),
+        text("ab"),
+        text_input("TT.MM.JJJJ", &app.schedule.valid_from)
+            .on_input(|s| Message::Schedule(ScheduleMsg::ValidFrom(s)))
+            .width(120),
+        styled_button(
+            Bootstrap::CalendarPlusFill,
+            "Neuer Stundenplan",
+            Message::Schedule(ScheduleMsg::New),
+            ColorType::Secondary,
+        ),
+    ]
+    .spacing(10)
+    .align_y(Alignment::Center);
+    if app.settings.schedules.0.len() > 1 {
+        header = header.push(styled_button(
+            Bootstrap::TrashthreeFill,
+            "Löschen",
+            Message::Schedule(ScheduleMsg::Delete),
+            ColorType::Danger,
+        ));
+    }
+
+    let mut periods = column![].spacing(2);
+    for (i, p) in app.schedule.periods.iter().enumerate() {
+        let mut days = row![].spacing(2);
+        for day in WEEKDAYS {
+            days = days.push(
+                button(text(weekday_name(day)).size(12))
+                    .on_press(Message::Schedule(ScheduleMsg::ToggleDay(i, day)))
+                    .style(match p.days.contains(&day) {
+                        true => button::primary,
+                        false => button::secondary,
+                    }),
+            );
+        }
+        let pause = saved
+            .and_then(|s| s.break_after(i))
+            .map(|b| format!("danach {} min Pause", b))
+            .unwrap_or_default();
+        periods = periods.push(
+            row![
+                text(format!("{}.", i + 1)).width(30),
+                text_input("HH:MM", &p.start)
+                    .on_input(move |s| Message::Schedule(ScheduleMsg::Start(i, s)))
+                    .width(80),
+                text_input("Min", &p.minutes)
+                    .on_input(move |s| Message::Schedule(ScheduleMsg::Minutes(i, s)))
+                    .width(60),
+                text("Minuten"),
+                days,
+                text(pause).style(themes::text_fg_sec).width(180),
+                button(
+                    text(icon_to_string(Bootstrap::TrashthreeFill))
+                        .font(ICON_FONT)
+                        .style(themes::text_fg_danger)
+                )
+                .on_press(Message::Schedule(ScheduleMsg::RemovePeriod(i)))
+                .style(button::text),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+    }
+
+    column![
+        text("Lektionszeiten").size(22).style(themes::text_fg),
+        text("Verspätungen und Absenzen verwenden die Zeiten, die an ihrem Datum galten.")
+            .style(themes::text_fg_sec),
+        text("Lektionen, die nur an einzelnen Tagen stattfinden, an den übrigen abwählen.")
+            .style(themes::text_fg_sec),
+        header,
+        periods,
+        row![
+            styled_button(
+                Bootstrap::PlusCircleFill,
+                "Lektion hinzufügen",
+                Message::Schedule(ScheduleMsg::AddPeriod),
+                ColorType::Secondary,
+            ),
+            styled_button(
+                Bootstrap::CheckSquareFill,
+                "Übernehmen",
+                Message::Schedule(ScheduleMsg::Apply),
+                ColorType::Succes,
+            ),
+        ]
+        .spacing(5),
+    ]
+    .spacing(5)
+    .into()
+}
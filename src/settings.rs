@@ -2,7 +2,11 @@ use crate::bootstrap::*;
 use crate::themes;
 use crate::themes::styled_button;
 use crate::themes::ColorType;
-use crate::{db, App, Message, ViewControl};
+use crate::storage::StorageKind;
+use crate::{
+    backup, calendar, db, merge, paths, retention, roster, schedule, subjects, timetable, unlock,
+    App, Message, ViewControl,
+};
 use chrono::prelude::*;
 use iced::event::{self, Event};
 use iced::widget::{
@@ -17,7 +21,7 @@ use iced::{Alignment, Element, Font, Length, Padding, Subscription, Task, Theme}
 use serde_derive::*;
 
 pub fn settings_view(app: &App) -> Element<Message> {
-    container(
+    container(scrollable(
         column![
             row![
                 button(
@@ -49,15 +53,31 @@ pub fn settings_view(app: &App) -> Element<Message> {
             .spacing(10)
             .align_y(Alignment::Center),
             text(format!(
-                "Working dir:  {}",
-                std::env::current_dir().unwrap().display()
+                "Datenordner ({}):  {}",
+                paths::location(),
+                paths::data_dir().display()
             )),
-            styled_button(
-                Bootstrap::FileEarmarkArrowDownFill,
-                "Neues Backup",
-                Message::BackupDB,
-                ColorType::Succes
-            ),
+            row![
+                text("Automatisch speichern:"),
+                pick_list(
+                    AutosaveDelay::all(),
+                    Some(AutosaveDelay(app.settings.autosave_secs)),
+                    Message::AutosaveSelected
+                ),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+            row![
+                text("Speicherformat:"),
+                pick_list(
+                    StorageKind::all(),
+                    Some(app.settings.storage),
+                    Message::SetStorage
+                ),
+                text(app.storage.path().to_string()).style(themes::text_fg_sec),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
             styled_button(
                 Bootstrap::DatabaseFillExclamation,
                 "Verspätungen Zurücksetzen",
@@ -69,32 +89,105 @@ pub fn settings_view(app: &App) -> Element<Message> {
                 "DB und Einstellungen Speichern",
                 Message::SaveDB,
                 ColorType::Succes,
-            )
+            ),
+            styled_button(
+                Bootstrap::ClipboardCheckFill,
+                "Datenprüfung",
+                Message::GoView(ViewControl::CHECK),
+                ColorType::Primary,
+            ),
+            horizontal_rule(1),
+            backup::backup_view(app),
+            horizontal_rule(1),
+            unlock::passphrase_view(app),
+            horizontal_rule(1),
+            retention::retention_view(app),
+            horizontal_rule(1),
+            schedule::schedule_view(app),
+            horizontal_rule(1),
+            calendar::calendar_view(app),
+            horizontal_rule(1),
+            merge::merge_view(app),
+            horizontal_rule(1),
+            roster::roster_view(app),
+            horizontal_rule(1),
+            subjects::subjects_view(app),
+            horizontal_rule(1),
+            timetable::timetable_view(app),
         ]
         .spacing(5)
         .padding(20),
     )
+    .style(themes::scrollbar_invis))
     .into()
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Settings {
     pub theme: String,
+    #[serde(default)]
+    pub storage: StorageKind,
+    /// Seconds without a change before unsaved data is written, 0 turns it off
+    #[serde(default = "default_autosave")]
+    pub autosave_secs: u64,
+    #[serde(default)]
+    pub retention: retention::Policy,
+    /// School days to hand in the excuse for an absence
+    #[serde(default = "default_excuse_days")]
+    pub excuse_days: u32,
+    #[serde(default)]
+    pub schedules: schedule::Schedules,
+    #[serde(default)]
+    pub calendar: calendar::Calendar,
+}
+
+fn default_autosave() -> u64 {
+    30
+}
+
+pub fn default_excuse_days() -> u32 {
+    10
 }
 
 impl Settings {
     pub fn new() -> Self {
         Settings {
             theme: "Dark".to_string(),
+            storage: StorageKind::default(),
+            autosave_secs: default_autosave(),
+            retention: retention::Policy::default(),
+            excuse_days: default_excuse_days(),
+            schedules: schedule::Schedules::default(),
+            calendar: calendar::Calendar::default(),
+        }
+    }
+}
+
+/// Choices for the autosave delay in the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutosaveDelay(pub u64);
+
+impl AutosaveDelay {
+    pub fn all() -> Vec<Self> {
+        [0, 10, 30, 60, 300].into_iter().map(AutosaveDelay).collect()
+    }
+}
+
+impl std::fmt::Display for AutosaveDelay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            0 => write!(f, "Aus"),
+            s if s < 60 => write!(f, "{} Sekunden", s),
+            s => write!(f, "{} Minuten", s / 60),
         }
     }
 }
 
-pub async fn load_from_file(path: &str) -> Settings {
-    if !Path::new(path).exists() {
+pub async fn load_from_file(path: String) -> Settings {
+    if !Path::new(&path).exists() {
         return Settings::new();
     }
-    let filecontent = fs::read_to_string(path).expect("Couldn't read file");
+    let filecontent = fs::read_to_string(&path).expect("Couldn't read file");
     let settings: Settings = serde_json::from_str(&filecontent).expect("Couldnt parse file");
     settings
 }
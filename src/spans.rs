@@ -0,0 +1,550 @@
+use crate::bootstrap::*;
+use crate::calendar::Calendar;
+use crate::db::{
+    AbsenceId, AbsenceRecord, Change, CohortId, DataBase, ExcuseStatus, LessonAbs, SpanId, Student,
+    StudentId, SubjectId,
+};
+use crate::schedule::{BellSchedule, Schedules};
+use crate::themes::{self, styled_button, ColorType};
+use crate::time;
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use iced::widget::{button, column, container, pick_list, row, text};
+use iced::{Alignment, Element, Task};
+use std::collections::{HashMap, HashSet};
+
+/// A student missing in the same lessons on one or more days in a row. It
+/// is not stored as such, the records of its lessons carry its id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub id: SpanId,
+    pub student: StudentId,
+    pub cohort: CohortId,
+    pub lesson: SubjectId,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Index of the first and last lesson of each day in its bell schedule
+    pub first: usize,
+    pub last: usize,
+}
+
+/// A lesson of the day, picked by its position in the bell schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub index: usize,
+    pub start: NaiveTime,
+}
+
+impl Slot {
+    pub fn all(schedule: &BellSchedule) -> Vec<Self> {
+        schedule
+            .starts()
+            .into_iter()
+            .enumerate()
+            .map(|(index, start)| Slot { index, start })
+            .collect()
+    }
+
+    /// Slot `index`, or the last one if the schedule has fewer lessons
+    pub fn at(schedule: &BellSchedule, index: usize) -> Option<Self> {
+        Slot::all(schedule)
+            .into_iter()
+            .take(index.saturating_add(1))
+            .next_back()
+    }
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{}. Lektion ({})",
+            self.index + 1,
+            self.start.format("%H:%M")
+        )
+    }
+}
+
+impl DataBase {
+    pub fn next_span_id(&self) -> SpanId {
+        self.absences
+            .iter()
+            .flat_map(|a| {
+                a.span
+                    .into_iter()
+                    .chain(a.records.iter().filter_map(|r| r.span))
+            })
+            .max()
+            .unwrap_or(0)
+            + 1
+    }
+
+    /// The multi-day absences of `cohort`, put back together from their lessons
+    pub fn spans(&self, cohort: Option<CohortId>, schedules: &Schedules) -> Vec<Span> {
+        let mut spans: Vec<Span> = vec![];
+        for a in self
+            .absences
+            .iter()
+            .filter(|a| cohort.is_none_or(|c| a.cohort == c))
+        {
+            let slot = schedules
+                .for_date(a.date)
+                .starts()
+                .iter()
+                .position(|t| *t == a.lesson_time)
+                .unwrap_or(0);
+            for r in &a.records {
+                let Some(id) = r.span else {
+                    continue;
+                };
+                match spans.iter_mut().find(|s| s.id == id) {
+                    Some(s) => {
+                        s.from = s.from.min(a.date);
+                        s.to = s.to.max(a.date);
+                        s.first = s.first.min(slot);
+                        s.last = s.last.max(slot);
+                    }
+                    None => spans.push(Span {
+                        id,
+                        student: r.student,
+                        cohort: a.cohort,
+                        lesson: a.lesson,
+                        from: a.date,
+                        to: a.date,
+                        first: slot,
+                        last: slot,
+                    }),
+                }
+            }
+        }
+        spans.sort_by_key(|s| (s.from, s.first));
+        spans
+    }
+
+    /// The changes that take back the multi-day absence `cancel` and record
+    /// `add`, either may be left out. They are worked out on a copy of the
+    /// absences, only the lessons that differ become changes. Also returns
+    /// how many lessons `add` marked.
+    pub fn span_changes(
+        &self,
+        cancel: Option<SpanId>,
+        add: Option<&Span>,
+        schedules: &Schedules,
+        calendar: &Calendar,
+        deadline_days: u32,
+    ) -> (Vec<Change>, usize) {
+        let mut absences = self.absences.clone();
+        if let Some(id) = cancel {
+            cancel_span(&mut absences, id);
+        }
+        let n = match add {
+            Some(span) => add_span(
+                self,
+                &mut absences,
+                span,
+                schedules,
+                calendar,
+                deadline_days,
+            ),
+            None => 0,
+        };
+        let old: HashMap<AbsenceId, &LessonAbs> = self.absences.iter().map(|a| (a.id, a)).collect();
+        let mut changes = vec![];
+        for a in &absences {
+            match old.get(&a.id) {
+                None => changes.push(Change::AddAbsence(a.clone())),
+                Some(o) if *o != a => changes.push(Change::UpdateAbsence(a.clone())),
+                Some(_) => (),
+            }
+        }
+        let kept: HashSet<AbsenceId> = absences.iter().map(|a| a.id).collect();
+        for a in self.absences.iter().filter(|a| !kept.contains(&a.id)) {
+            changes.push(Change::DeleteAbsence(a.id));
+        }
+        (changes, n)
+    }
+}
+
+/// Marks the student of `span` missing in every lesson it covers.
+/// Lessons nobody recorded yet are created with everyone else present
+/// and the subject the timetable has for them.
+/// Returns how many lessons were changed.
+fn add_span(
+    db: &DataBase,
+    absences: &mut Vec<LessonAbs>,
+    span: &Span,
+    schedules: &Schedules,
+    calendar: &Calendar,
+    deadline_days: u32,
+) -> usize {
+    // The excuse is due counted from the day the student is back
+    let deadline = time::add_school_days(span.to, deadline_days, calendar);
+    let roster: Vec<StudentId> = db.roster(Some(span.cohort)).iter().map(|s| s.id).collect();
+    let mut changed = 0;
+    let mut day = span.from;
+    while day <= span.to {
+        if !calendar.is_school_day(day) {
+            day = day.succ_opt().expect("Theres no tommorow?");
+            continue;
+        }
+        let schedule = schedules.for_date(day);
+        let starts = schedule.starts();
+        let last = span.last.min(starts.len().saturating_sub(1));
+        let held: Vec<usize> = (span.first..=last)
+            .filter(|i| schedule.is_held(*i, day.weekday()))
+            .collect();
+        for slot in held {
+            let t = starts[slot];
+            let existing = absences
+                .iter()
+                .position(|a| a.cohort == span.cohort && a.date == day && a.lesson_time == t);
+            let i = match existing {
+                Some(i) => i,
+                None => {
+                    let id = absences.iter().map(|a| a.id).max().unwrap_or(0) + 1;
+                    // The subject picked in the form only stands in
+                    // where the timetable has nothing
+                    let lesson = db
+                        .planned_subject(Some(span.cohort), day.weekday(), slot)
+                        .unwrap_or(span.lesson);
+                    absences.push(LessonAbs {
+                        id,
+                        cohort: span.cohort,
+                        present: roster.clone(),
+                        lesson,
+                        lesson_time: t,
+                        first_lesson: schedule.starts_on(day).first() == Some(&t),
+                        date: day,
+                        archived_absent: None,
+                        records: vec![],
+                        span: Some(span.id),
+                    });
+                    absences.len() - 1
+                }
+            };
+            let a = &mut absences[i];
+            // Lessons the student already missed stay as they are
+            if a.record(span.student).is_some() {
+                continue;
+            }
+            a.present.retain(|p| *p != span.student);
+            a.records.push(AbsenceRecord {
+                student: span.student,
+                status: ExcuseStatus::Open,
+                note: String::new(),
+                deadline,
+                span: Some(span.id),
+            });
+            changed += 1;
+        }
+        day = day.succ_opt().expect("Theres no tommorow?");
+    }
+    changed
+}
+
+/// Takes a multi-day absence back. Lessons that were only created for it
+/// and nobody else missed are removed again.
+fn cancel_span(absences: &mut Vec<LessonAbs>, id: SpanId) {
+    for a in absences.iter_mut() {
+        let (ours, rest): (Vec<AbsenceRecord>, Vec<AbsenceRecord>) =
+            a.records.drain(..).partition(|r| r.span == Some(id));
+        a.records = rest;
+        a.present.extend(ours.iter().map(|r| r.student));
+    }
+    absences.retain(|a| a.span != Some(id) || !a.absentees().is_empty());
+}
+
+#[derive(Debug, Clone)]
+pub struct SpanState {
+    editing: Option<SpanId>,
+    student: Option<Student>,
+    from: NaiveDate,
+    to: NaiveDate,
+    first: usize,
+    last: usize,
+    confirm_cancel: Option<SpanId>,
+}
+
+impl Default for SpanState {
+    fn default() -> Self {
+        SpanState {
+            editing: None,
+            student: None,
+            from: time::get_today(),
+            to: time::get_today(),
+            first: 0,
+            // Up to the last lesson, however many the day has
+            last: usize::MAX,
+            confirm_cancel: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SpanMsg {
+    Student(Student),
+    FromPrev,
+    FromNext,
+    ToPrev,
+    ToNext,
+    First(Slot),
+    Last(Slot),
+    /// Only the chosen lessons of today
+    Today,
+    Save,
+    Edit(SpanId),
+    Cancel(SpanId),
+    ConfirmCancel,
+    Abort,
+}
+
+pub fn update_span(app: &mut App, msg: SpanMsg) -> Task<Message> {
+    let form = &mut app.span;
+    let calendar = &app.settings.calendar;
+    match msg {
+        SpanMsg::Student(s) => form.student = Some(s),
+        SpanMsg::FromPrev => form.from = calendar.prev_school_day(form.from),
+        SpanMsg::FromNext => {
+            form.from = calendar.next_school_day(form.from);
+            form.to = form.to.max(form.from);
+        }
+        SpanMsg::ToPrev => {
+            form.to = calendar.prev_school_day(form.to);
+            form.from = form.from.min(form.to);
+        }
+        SpanMsg::ToNext => form.to = calendar.next_school_day(form.to),
+        SpanMsg::First(s) => {
+            form.first = s.index;
+            form.last = form.last.max(s.index);
+        }
+        SpanMsg::Last(s) => {
+            form.last = s.index;
+            form.first = form.first.min(s.index);
+        }
+        SpanMsg::Today => {
+            form.from = time::get_today();
+            form.to = form.from;
+        }
+        SpanMsg::Edit(id) => {
+            let spans = app.db.spans(None, &app.settings.schedules);
+            if let Some(span) = spans.into_iter().find(|s| s.id == id) {
+                form.student = app.db.student(span.student).cloned();
+                form.from = span.from;
+                form.to = span.to;
+                form.first = span.first;
+                form.last = span.last;
+                form.editing = Some(id);
+            }
+        }
+        SpanMsg::Abort => match form.confirm_cancel {
+            Some(_) => form.confirm_cancel = None,
+            None => *form = SpanState::default(),
+        },
+        SpanMsg::Cancel(id) => form.confirm_cancel = Some(id),
+        SpanMsg::ConfirmCancel => {
+            if let Some(id) = form.confirm_cancel.take() {
+                let name = app
+                    .db
+                    .spans(None, &app.settings.schedules)
+                    .iter()
+                    .find(|s| s.id == id)
+                    .map(|s| app.db.student_name(s.student))
+                    .unwrap_or_default();
+                let settings = &app.settings;
+                let (changes, _) = app.db.span_changes(
+                    Some(id),
+                    None,
+                    &settings.schedules,
+                    &settings.calendar,
+                    settings.excuse_days,
+                );
+                app.commit_undoable(Change::Batch(changes), "Absenz aufgehoben", &name);
+            }
+        }
+        SpanMsg::Save => return save(app),
+    }
+    Task::none()
+}
+
+fn save(app: &mut App) -> Task<Message> {
+    let (Some(student), Some(cohort)) = (app.span.student.clone(), app.cohort) else {
+        app.notify(Toast::new("Fehler", "Person auswählen", Status::Danger));
+        return Task::none();
+    };
+    let id = app.span.editing.unwrap_or_else(|| app.db.next_span_id());
+    let span = Span {
+        id,
+        student: student.id,
+        cohort,
+        lesson: app.abs.lesson,
+        from: app.span.from,
+        to: app.span.to,
+        first: app.span.first,
+        last: app.span.last,
+    };
+    let settings = &app.settings;
+    let (changes, n) = app.db.span_changes(
+        app.span.editing,
+        Some(&span),
+        &settings.schedules,
+        &settings.calendar,
+        settings.excuse_days,
+    );
+    if n == 0 {
+        app.notify(Toast::new(
+            "Nicht erfasst",
+            "Keine Schultage im gewählten Zeitraum oder schon überall abwesend",
+            Status::Danger,
+        ));
+        return Task::none();
+    }
+    app.commit_undoable(
+        Change::Batch(changes),
+        "Absenz erfasst",
+        &format!("{}: {} Lektionen", student, n),
+    );
+    app.span = SpanState::default();
+    Task::none()
+}
+
+fn date_stepper<'a>(
+    label: &'a str,
+    date: NaiveDate,
+    prev: SpanMsg,
+    next: SpanMsg,
+) -> Element<'a, Message> {
+    container(
+        row![
+            text(label),
+            button(text(icon_to_string(Bootstrap::DashCircleFill)).font(ICON_FONT))
+                .on_press(Message::Span(prev))
+                .style(button::text),
+            text(date.format("%a %d.%m.%Y").to_string()).style(themes::text_fg),
+            button(text(icon_to_string(Bootstrap::PlusCircleFill)).font(ICON_FONT))
+                .on_press(Message::Span(next))
+                .style(button::text),
+        ]
+        .spacing(2)
+        .align_y(Alignment::Center),
+    )
+    .style(container::bordered_box)
+    .into()
+}
+
+/// Form for absences over whole days or several lessons, with the ones
+/// already recorded for the class below it
+pub fn span_view(app: &App) -> Element<Message> {
+    let form = &app.span;
+    let schedule = app.settings.schedules.for_date(form.from);
+    let mut actions = row![styled_button(
+        Bootstrap::CalendarRangeFill,
+        match form.editing {
+            Some(_) => "Änderung speichern",
+            None => "Zeitraum erfassen",
+        },
+        Message::Span(SpanMsg::Save),
+        ColorType::Succes,
+    )]
+    .spacing(5);
+    if form.editing.is_some() {
+        actions = actions.push(styled_button(
+            Bootstrap::XSquareFill,
+            "Abbrechen",
+            Message::Span(SpanMsg::Abort),
+            ColorType::Secondary,
+        ));
+    }
+
+    let mut list = column![].spacing(2);
+    for span in app.db.spans(app.cohort, &app.settings.schedules) {
+        let schedule = app.settings.schedules.for_date(span.from);
+        let slot = |i| {
+            Slot::at(schedule, i)
+                .map(|s| s.to_string())
+                .unwrap_or_default()
+        };
+        let mut line = row![
+            text(app.db.student_name(span.student)).width(180),
+            text(format!(
+                "{} bis {}, {} bis {}",
+                span.from.format("%d.%m.%Y"),
+                span.to.format("%d.%m.%Y"),
+                slot(span.first),
+                slot(span.last)
+            ))
+            .style(themes::text_fg_sec),
+            button(text(icon_to_string(Bootstrap::PencilFill)).font(ICON_FONT))
+                .on_press(Message::Span(SpanMsg::Edit(span.id)))
+                .style(button::text),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+        line = match form.confirm_cancel == Some(span.id) {
+            true => line
+                .push(text("Aufheben?").style(themes::text_fg_danger))
+                .push(styled_button(
+                    Bootstrap::TrashthreeFill,
+                    "Ja",
+                    Message::Span(SpanMsg::ConfirmCancel),
+                    ColorType::Danger,
+                ))
+                .push(styled_button(
+                    Bootstrap::XSquareFill,
+                    "Nein",
+                    Message::Span(SpanMsg::Abort),
+                    ColorType::Secondary,
+                )),
+            false => line.push(
+                button(
+                    text(icon_to_string(Bootstrap::TrashthreeFill))
+                        .font(ICON_FONT)
+                        .style(themes::text_fg_danger),
+                )
+                .on_press(Message::Span(SpanMsg::Cancel(span.id)))
+                .style(button::text),
+            ),
+        };
+        list = list.push(line);
+    }
+
+    column![
+        text("Ganz- und mehrtägige Absenzen")
+            .size(20)
+            .style(themes::text_fg),
+        row![
+            pick_list(app.db.roster(app.cohort), form.student.clone(), |s| {
+                Message::Span(SpanMsg::Student(s))
+            })
+            .placeholder("Person"),
+            date_stepper("Von", form.from, SpanMsg::FromPrev, SpanMsg::FromNext),
+            date_stepper("Bis", form.to, SpanMsg::ToPrev, SpanMsg::ToNext),
+            button(text("Heute"))
+                .on_press(Message::Span(SpanMsg::Today))
+                .style(button::secondary),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center),
+        row![
+            pick_list(Slot::all(schedule), Slot::at(schedule, form.first), |s| {
+                Message::Span(SpanMsg::First(s))
+            }),
+            text("bis"),
+            pick_list(Slot::all(schedule), Slot::at(schedule, form.last), |s| {
+                Message::Span(SpanMsg::Last(s))
+            }),
+            text(format!(
+                "Fach für neue Lektionen: {}",
+                app.db.subject_name(app.abs.lesson)
+            ))
+            .style(themes::text_fg_sec),
+            actions,
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center),
+        list,
+    ]
+    .spacing(5)
+    .padding(5)
+    .into()
+}
@@ -0,0 +1,311 @@
+use crate::db::{Change, DataBase, DataBaseError, Entry, Lates, LessonAbs};
+use crate::migrate::{self, SCHEMA_VERSION};
+use crate::storage::Storage;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OpenFlags};
+use serde_json::Value;
+use std::sync::{Mutex, MutexGuard};
+
+// Records are kept as JSON so they go through the same migrations as db.json,
+// the columns next to them only exist to be indexed.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+CREATE TABLE IF NOT EXISTS entries (
+    id INTEGER PRIMARY KEY,
+    person INTEGER NOT NULL,
+    lesson INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS entries_person ON entries (person);
+CREATE INDEX IF NOT EXISTS entries_lesson ON entries (lesson);
+CREATE INDEX IF NOT EXISTS entries_date ON entries (date);
+CREATE TABLE IF NOT EXISTS absences (
+    id INTEGER PRIMARY KEY,
+    cohort INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS absences_cohort_date ON absences (cohort, date);
+";
+
+impl From<rusqlite::Error> for DataBaseError {
+    fn from(value: rusqlite::Error) -> Self {
+        DataBaseError::Sqlite {
+            path: None,
+            message: value.to_string(),
+        }
+    }
+}
+
+/// Embedded SQLite file that stores every change as it happens
+pub struct SqliteStorage {
+    path: String,
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self, DataBaseError> {
+        let conn = Connection::open(path).map_err(|e| DataBaseError::from(e).at(path))?;
+        conn.execute_batch(SCHEMA).map_err(|e| DataBaseError::from(e).at(path))?;
+        Ok(SqliteStorage {
+            path: path.to_string(),
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Reads the file at `path` without creating, migrating or saving it
+    pub fn read_only(path: &str) -> Result<DataBase, DataBaseError> {
+        SqliteStorage::read_only_inner(path).map_err(|e| e.at(path))
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> Result<(DataBase, bool), DataBaseError> {
+        self.load_inner().map_err(|e| e.at(&self.path))
+    }
+
+    fn save(&self, db: &DataBase) -> Result<(), DataBaseError> {
+        self.save_inner(db).map_err(|e| e.at(&self.path))
+    }
+
+    fn apply(&self, change: &Change) -> Result<(), DataBaseError> {
+        self.apply_inner(change).map_err(|e| e.at(&self.path))
+    }
+
+    fn saves_changes(&self) -> bool {
+        true
+    }
+
+    fn lates_per_person(
+        &self,
+        range: Option<(NaiveDate, NaiveDate)>,
+    ) -> Option<Result<Vec<Lates>, DataBaseError>> {
+        Some(
+            self.lates_per_person_inner(range)
+                .map_err(|e| e.at(&self.path)),
+        )
+    }
+
+    fn entries_between(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Option<Result<Vec<Entry>, DataBaseError>> {
+        Some(
+            self.entries_between_inner(from, to)
+                .map_err(|e| e.at(&self.path)),
+        )
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl SqliteStorage {
+    fn load_inner(&self) -> Result<(DataBase, bool), DataBaseError> {
+        let mut doc = read_document(&self.conn())?;
+        if doc.get("schema_version").is_none() {
+            // Freshly created file
+            return Ok((DataBase::empty(), false));
+        }
+        let version = migrate::version_of(&doc);
+        migrate::migrate(&mut doc)?;
+        let db: DataBase = serde_json::from_value(doc)?;
+        if version < SCHEMA_VERSION {
+            // Only the JSON file can be encrypted, so the copy holds nothing
+            // the database itself doesn't
+            std::fs::copy(&self.path, format!("{}.v{}.bak", self.path, version))?;
+            self.save(&db)?;
+        }
+        Ok((db, false))
+    }
+
+    fn read_only_inner(path: &str) -> Result<DataBase, DataBaseError> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut doc = read_document(&conn)?;
+        if doc.get("schema_version").is_none() {
+            return Err(DataBaseError::Other(
+                "Die Datei enthält keine Datenbank".to_string(),
+            ));
+        }
+        migrate::migrate(&mut doc)?;
+        Ok(serde_json::from_value(doc)?)
+    }
+
+    fn lates_per_person_inner(
+        &self,
+        range: Option<(NaiveDate, NaiveDate)>,
+    ) -> Result<Vec<Lates>, DataBaseError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT person, COUNT(*), COALESCE(SUM(json_extract(data, '$.delay_min')), 0)
+             FROM entries WHERE ?1 IS NULL OR date BETWEEN ?1 AND ?2 GROUP BY person",
+        )?;
+        let (from, to) = match range {
+            Some((from, to)) => (Some(from.to_string()), Some(to.to_string())),
+            None => (None, None),
+        };
+        let rows = stmt.query_map(params![from, to], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?;
+        let mut out = vec![];
+        for row in rows {
+            out.push(row?);
+        }
+        Ok(out)
+    }
+
+    fn entries_between_inner(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<Entry>, DataBaseError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT data FROM entries WHERE date BETWEEN ?1 AND ?2 ORDER BY date, id",
+        )?;
+        let rows = stmt.query_map(params![from.to_string(), to.to_string()], |r| {
+            r.get::<_, String>(0)
+        })?;
+        let mut out = vec![];
+        for row in rows {
+            out.push(serde_json::from_str(&row?)?);
+        }
+        Ok(out)
+    }
+
+    fn save_inner(&self, db: &DataBase) -> Result<(), DataBaseError> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        tx.execute_batch("DELETE FROM meta; DELETE FROM entries; DELETE FROM absences;")?;
+        write_meta(&tx, db)?;
+        for e in &db.data {
+            write_entry(&tx, e)?;
+        }
+        for a in &db.absences {
+            write_absence(&tx, a)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn apply_inner(&self, change: &Change) -> Result<(), DataBaseError> {
+        if let Change::Replace(db) = change {
+            return self.save_inner(db);
+        }
+        let mut conn = self.conn();
+        // A batch is stored completely or not at all
+        let tx = conn.transaction()?;
+        write_change(&tx, change)?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn write_change(conn: &Connection, change: &Change) -> Result<(), DataBaseError> {
+    match change {
+        Change::AddEntry(e) | Change::UpdateEntry(e) => write_entry(conn, e)?,
+        Change::DeleteEntry(id) => {
+            conn.execute("DELETE FROM entries WHERE id = ?1", params![*id as i64])?;
+        }
+        Change::AddAbsence(a) | Change::UpdateAbsence(a) => write_absence(conn, a)?,
+        Change::DeleteAbsence(id) => {
+            conn.execute("DELETE FROM absences WHERE id = ?1", params![*id as i64])?;
+        }
+        Change::SetCohorts(c) => write_value(conn, "cohorts", serde_json::to_value(c)?)?,
+        Change::SetStudents(s) => write_value(conn, "students", serde_json::to_value(s)?)?,
+        Change::SetSubjects(s) => write_value(conn, "subjects", serde_json::to_value(s)?)?,
+        Change::Replace(_) => (),
+        Change::Batch(changes) => {
+            for c in changes {
+                write_change(conn, c)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Puts the document `DataBase` serialises to back together
+fn read_document(conn: &Connection) -> Result<Value, DataBaseError> {
+    let mut doc = serde_json::Map::new();
+    {
+        let mut stmt = conn.prepare("SELECT key, value FROM meta")?;
+        let rows = stmt.query_map([], |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (key, value) = row?;
+            doc.insert(key, serde_json::from_str(&value)?);
+        }
+    }
+    doc.insert(
+        "data".to_string(),
+        Value::Array(read_rows(conn, "SELECT data FROM entries ORDER BY id")?),
+    );
+    doc.insert(
+        "absences".to_string(),
+        Value::Array(read_rows(conn, "SELECT data FROM absences ORDER BY id")?),
+    );
+    Ok(Value::Object(doc))
+}
+
+fn read_rows(conn: &Connection, sql: &str) -> Result<Vec<Value>, DataBaseError> {
+    let mut stmt = conn.prepare(sql)?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    let mut out = vec![];
+    for row in rows {
+        out.push(serde_json::from_str(&row?)?);
+    }
+    Ok(out)
+}
+
+/// Everything but the two record lists goes into `meta`, one row per field
+fn write_meta(conn: &Connection, db: &DataBase) -> Result<(), DataBaseError> {
+    if let Value::Object(fields) = serde_json::to_value(db)? {
+        for (key, value) in fields {
+            if key != "data" && key != "absences" {
+                write_value(conn, &key, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_value(conn: &Connection, key: &str, value: Value) -> Result<(), DataBaseError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+        params![key, value.to_string()],
+    )?;
+    Ok(())
+}
+
+fn write_entry(conn: &Connection, e: &Entry) -> Result<(), DataBaseError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO entries (id, person, lesson, date, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            e.id as i64,
+            e.person,
+            e.lesson,
+            e.date.to_string(),
+            serde_json::to_string(e)?
+        ],
+    )?;
+    Ok(())
+}
+
+fn write_absence(conn: &Connection, a: &LessonAbs) -> Result<(), DataBaseError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO absences (id, cohort, date, data) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            a.id as i64,
+            a.cohort,
+            a.date.to_string(),
+            serde_json::to_string(a)?
+        ],
+    )?;
+    Ok(())
+}
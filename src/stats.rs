@@ -1,7 +1,8 @@
 use crate::bootstrap::*;
-use crate::db::Class;
+use crate::calendar::Term;
+use crate::db::{DataBase, Lates, Student, StudentId};
 use crate::themes::{self, text_fg, text_fg_succes};
-use crate::{App, Message, ViewControl};
+use crate::{list, App, Message, ViewControl};
 use iced::widget::{
     button, checkbox, column, combo_box, container, horizontal_rule, horizontal_space, pick_list,
     row, scrollable, slider, stack, text, text_input, toggler, tooltip, vertical_rule,
@@ -14,8 +15,29 @@ use serde_derive::{Deserialize, Serialize};
 pub struct StatState {
     person: Option<Ranking>,
     subject: Option<Ranking>,
-    detail_person: Option<Class>,
+    detail_person: Option<StudentId>,
     detail_view: bool,
+    all_classes: bool,
+    term: Option<Term>,
+    #[serde(skip)]
+    data: StatsData,
+}
+
+/// What the statistics are computed on, kept from one redraw to the next
+/// since the storage backend's queries are too slow to run in `view`
+#[derive(Debug)]
+struct StatsData {
+    db: DataBase,
+    lates: Vec<Lates>,
+}
+
+impl Default for StatsData {
+    fn default() -> Self {
+        StatsData {
+            db: DataBase::empty(),
+            lates: vec![],
+        }
+    }
 }
 
 impl StatState {
@@ -23,8 +45,11 @@ impl StatState {
         StatState {
             person: Some(Ranking::Number),
             subject: Some(Ranking::Number),
-            detail_person: Some(Class::Anina),
+            detail_person: None,
             detail_view: false,
+            all_classes: false,
+            term: None,
+            data: StatsData::default(),
         }
     }
 }
@@ -36,16 +61,18 @@ impl Default for StatState {
 
 #[derive(Debug, Clone)]
 pub enum StatsMessage {
-    PersonSelected(Class),
-    PersonSelectedCV(Class),
+    PersonSelected(Student),
+    PersonSelectedCV(StudentId),
     PersonRankingType(Ranking),
     SubjectRankingType(Ranking),
+    AllClasses(bool),
+    Term(TermFilter),
     OverView,
 }
 
 pub fn update_stats(app: &mut App, msg: StatsMessage) -> Task<Message> {
     match msg {
-        StatsMessage::PersonSelected(class) => app.stats.detail_person = Some(class),
+        StatsMessage::PersonSelected(student) => app.stats.detail_person = Some(student.id),
         StatsMessage::PersonSelectedCV(class) => {
             app.stats.detail_person = Some(class);
             app.stats.detail_view = true;
@@ -53,10 +80,34 @@ pub fn update_stats(app: &mut App, msg: StatsMessage) -> Task<Message> {
         StatsMessage::OverView => app.stats.detail_view = false,
         StatsMessage::PersonRankingType(ranking) => app.stats.person = Some(ranking),
         StatsMessage::SubjectRankingType(ranking) => app.stats.subject = Some(ranking),
+        StatsMessage::AllClasses(all) => {
+            app.stats.all_classes = all;
+            refresh(app);
+        }
+        StatsMessage::Term(t) => {
+            app.stats.term = t.0;
+            refresh(app);
+        }
     }
     Task::none()
 }
 
+/// Runs the queries for the statistics screen while it is open. `update`
+/// calls this when the screen opens and when the data, the class or the
+/// term shown changes.
+pub fn refresh(app: &mut App) {
+    if !matches!(app.view, ViewControl::STATS) {
+        return;
+    }
+    let db = stats_db(app);
+    let range = app.stats.term.as_ref().map(|t| (t.from, t.to));
+    let lates = match app.storage.lates_per_person(range) {
+        Some(Ok(lates)) => lates,
+        _ => db.lates_per_person(),
+    };
+    app.stats.data = StatsData { db, lates };
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub enum Ranking {
     Number,
@@ -78,17 +129,70 @@ impl std::fmt::Display for Ranking {
     }
 }
 
+/// Entry of the term picker, `None` for all the data
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermFilter(Option<Term>);
+
+impl std::fmt::Display for TermFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.0 {
+            Some(t) => write!(f, "{}", t),
+            None => write!(f, "Alle Semester"),
+        }
+    }
+}
+
+fn term_picker(app: &App) -> Element<Message> {
+    let mut terms = vec![TermFilter(None)];
+    terms.extend(
+        app.settings
+            .calendar
+            .terms
+            .iter()
+            .cloned()
+            .map(|t| TermFilter(Some(t))),
+    );
+    pick_list(terms, Some(TermFilter(app.stats.term.clone())), |t| {
+        Message::Stats(StatsMessage::Term(t))
+    })
+    .into()
+}
+
+/// The data the statistics are computed on: the selected class or all
+/// classes, limited to the selected term
+fn stats_db(app: &App) -> DataBase {
+    let mut db = if app.stats.all_classes {
+        app.db.scoped(None)
+    } else {
+        app.db.scoped(app.cohort)
+    };
+    if let Some(term) = &app.stats.term {
+        match app.storage.entries_between(term.from, term.to) {
+            Some(Ok(entries)) => {
+                db.data = entries
+                    .into_iter()
+                    .filter(|e| db.students.iter().any(|s| s.id == e.person))
+                    .collect()
+            }
+            _ => db.data.retain(|e| term.contains(e.date)),
+        }
+        db.absences.retain(|a| term.contains(a.date));
+    }
+    db
+}
+
 pub fn profile_stats(app: &App) -> Element<Message> {
-    let person_text = match &app.stats.detail_person {
+    let db = &app.stats.data.db;
+    let person_text = match app.stats.detail_person {
         None => "Niemandem".to_string(),
-        Some(p) => p.to_string(),
+        Some(p) => db.student_name(p),
     };
-    let lates: Element<Message> = match &app.stats.detail_person {
-        Some(p) => lates_person(app, p.clone()),
+    let lates: Element<Message> = match app.stats.detail_person {
+        Some(p) => lates_person(db, p),
         None => text("Keine Verspätungen").into(),
     };
-    let facts: Element<Message> = match &app.stats.detail_person {
-        Some(p) => funfacts_person(app, p.clone()),
+    let facts: Element<Message> = match app.stats.detail_person {
+        Some(p) => funfacts_person(db, p),
         None => text("Keine Verspätungen").into(),
     };
 
@@ -107,8 +211,10 @@ pub fn profile_stats(app: &App) -> Element<Message> {
             )
             .on_press(Message::Stats(StatsMessage::OverView))
             .style(button::text),
-            horizontal_space()
-        ],
+            horizontal_space(),
+            term_picker(app),
+        ]
+        .align_y(Alignment::Center),
         horizontal_rule(1),
         horizontal_space().height(5),
         column![
@@ -117,9 +223,13 @@ pub fn profile_stats(app: &App) -> Element<Message> {
                     .size(20)
                     .style(text_fg),
                 horizontal_space(),
-                pick_list(Class::all(), app.stats.detail_person.clone(), |p| {
-                    Message::Stats(StatsMessage::PersonSelected(p))
-                }),
+                pick_list(
+                    db.roster(None),
+                    app.stats
+                        .detail_person
+                        .and_then(|p| db.student(p).cloned()),
+                    |p| Message::Stats(StatsMessage::PersonSelected(p))
+                ),
             ]
             .align_y(Alignment::Center)
             .spacing(5),
@@ -132,8 +242,8 @@ pub fn profile_stats(app: &App) -> Element<Message> {
     .into()
 }
 
-fn funfacts_person(app: &App, person: Class) -> Element<Message> {
-    let stats = app.db.get_profile_stats(person);
+fn funfacts_person<'a>(db: &DataBase, person: StudentId) -> Element<'a, Message> {
+    let stats = db.get_profile_stats(person);
     let total = row![
         text("Verspätungen").size(18),
         horizontal_space(),
@@ -204,25 +314,26 @@ fn funfacts_person(app: &App, person: Class) -> Element<Message> {
     .into()
 }
 
-fn lates_person(app: &App, person: Class) -> Element<Message> {
+fn lates_person<'a>(db: &DataBase, person: StudentId) -> Element<'a, Message> {
     let mut lates = column![row![
         text("Verspätungen").style(themes::text_fg_sec).size(20),
         horizontal_space()
     ]];
-    for entry in &app.db.data {
+    for entry in &db.data {
         if entry.person != person {
             continue;
         }
-        let t = text(entry.person.to_string());
+        let t = text(db.student_name(entry.person));
         lates = lates.push(column![
             row![
                 t.size(20).style(text::success),
                 horizontal_space(),
-                text(entry.lesson.to_string()).style(|theme: &Theme| text::primary(theme)),
+                list::subject_text(db, entry.lesson),
                 text(format!(" Erste Lektion: {} ", entry.first_lesson)),
                 text(format!("{} Min", entry.delay_min))
                     .style(|theme: &Theme| text::primary(theme)),
                 horizontal_space(),
+                list::reason_text(entry),
                 column![
                     text(entry.lesson_time.to_string()),
                     text(entry.date.to_string())
@@ -235,9 +346,16 @@ fn lates_person(app: &App, person: Class) -> Element<Message> {
                             .size(22)
                             .style(themes::text_fg_danger)
                     )
-                    .on_press(Message::DLEntry(entry.clone()))
+                    .on_press(Message::DLEntry(entry.id))
+                    .style(button::text),
+                    button(
+                        text(icon_to_string(Bootstrap::PencilFill))
+                            .font(ICON_FONT)
+                            .size(22)
+                            .style(themes::text_fg)
+                    )
+                    .on_press(Message::Edit(entry.id))
                     .style(button::text),
-                    //button("Bearbeiten").on_press(Message::Edit)
                 ]
                 .align_x(Alignment::Center)
                 .spacing(5)
@@ -257,6 +375,7 @@ fn lates_person(app: &App, person: Class) -> Element<Message> {
 
 pub fn stats_view(app: &App) -> Element<Message> {
     if !app.stats.detail_view {
+        let db = &app.stats.data.db;
         row![column![
             row![
                 button(
@@ -272,16 +391,22 @@ pub fn stats_view(app: &App) -> Element<Message> {
                 )
                 .on_press(Message::BackView)
                 .style(button::text),
-                horizontal_space()
-            ],
+                horizontal_space(),
+                term_picker(app),
+                toggler(app.stats.all_classes)
+                    .on_toggle(|all| Message::Stats(StatsMessage::AllClasses(all)))
+                    .label("Alle Klassen"),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
             horizontal_rule(1),
             horizontal_space().height(5),
             row![
-                ranking_person(app),
+                ranking_person(app, db),
                 //vertical_rule(1),
-                ranking_lesson(app),
+                ranking_lesson(app, db),
                 //vertical_rule(1),
-                funfacts(app),
+                funfacts(db),
             ]
             .spacing(5)
             .align_y(Alignment::Start)
@@ -296,25 +421,25 @@ pub fn stats_view(app: &App) -> Element<Message> {
     }
 }
 
-fn funfacts(app: &App) -> Element<Message> {
-    let avg = text(format!("Durchschnitt: {}", app.db.average_delay()));
+fn funfacts<'a>(db: &DataBase) -> Element<'a, Message> {
+    let avg = text(format!("Durchschnitt: {}", db.average_delay()));
     let min = text(format!(
         "Minimum: {}",
-        app.db.data.iter().map(|x| x.delay_min).min().unwrap()
+        db.data.iter().map(|x| x.delay_min).min().unwrap_or(0)
     ));
-    let sum_min = text(format!("Summe: {}min", app.db.sum_min()));
+    let sum_min = text(format!("Summe: {}min", db.sum_min()));
     let max = text(format!(
         "Maximum: {}",
-        app.db.data.iter().map(|x| x.delay_min).max().unwrap()
+        db.data.iter().map(|x| x.delay_min).max().unwrap_or(0)
     ));
-    let total = text(format!("Total: {}", app.db.data.len()));
+    let total = text(format!("Total: {}", db.data.len()));
     let first_percent = text(format!(
         "Erste Lektion des Tages: {}%",
-        app.db.get_percent_first_lesson()
+        db.get_percent_first_lesson()
     ));
     let penalties = text(format!(
         "Theoretische Strafstunden: {}",
-        app.db.total_penalties()
+        db.total_penalties()
     ));
     column![
         row![
@@ -334,10 +459,9 @@ fn funfacts(app: &App) -> Element<Message> {
     .into()
 }
 
-fn ranking_lesson(app: &App) -> Element<Message> {
+fn ranking_lesson<'a>(app: &'a App, db: &DataBase) -> Element<'a, Message> {
     let mut ranking = column![];
-    for (i, p) in app
-        .db
+    for (i, p) in db
         .ranking_vec_lesson(app.stats.subject.clone())
         .into_iter()
         .enumerate()
@@ -383,11 +507,10 @@ fn ranking_lesson(app: &App) -> Element<Message> {
     .into()
 }
 
-fn ranking_person(app: &App) -> Element<Message> {
+fn ranking_person<'a>(app: &'a App, db: &DataBase) -> Element<'a, Message> {
     let mut ranking = column![];
-    for (i, p) in app
-        .db
-        .ranking_vec(app.stats.person.clone())
+    for (i, p) in db
+        .ranking_vec(&app.stats.data.lates, app.stats.person.clone())
         .into_iter()
         .enumerate()
     {
@@ -400,7 +523,7 @@ fn ranking_person(app: &App) -> Element<Message> {
                         .style(text_fg_succes)
                         .font(ICON_FONT)
                 )
-                .on_press(Message::Stats(StatsMessage::PersonSelectedCV(p.0)))
+                .on_press(Message::Stats(StatsMessage::PersonSelectedCV(p.0.id)))
                 .style(button::text),
                 horizontal_space(),
                 text(format!("{}min", p.2.to_string()))
@@ -0,0 +1,132 @@
+use crate::db::{Change, DataBase, DataBaseError, Entry, Lates};
+use chrono::NaiveDate;
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum StorageKind {
+    #[default]
+    Json,
+    Sqlite,
+}
+
+impl StorageKind {
+    pub fn all() -> Vec<Self> {
+        vec![StorageKind::Json, StorageKind::Sqlite]
+    }
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            StorageKind::Json => "db.json",
+            StorageKind::Sqlite => "db.sqlite",
+        }
+    }
+    /// Guesses the format of a file from its extension
+    pub fn from_path(path: &str) -> Self {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".sqlite") || lower.ends_with(".sqlite3") || lower.ends_with(".db") {
+            StorageKind::Sqlite
+        } else {
+            StorageKind::Json
+        }
+    }
+}
+
+impl std::fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StorageKind::Json => write!(f, "JSON-Datei"),
+            StorageKind::Sqlite => write!(f, "SQLite"),
+        }
+    }
+}
+
+/// Where the database lives. Backends that can store a single change do so in
+/// `apply`, the others only write on the next `save`.
+pub trait Storage: Send + Sync {
+    /// The flag is true if the data had to be recovered from a fallback copy
+    fn load(&self) -> Result<(DataBase, bool), DataBaseError>;
+    fn save(&self, db: &DataBase) -> Result<(), DataBaseError>;
+    fn apply(&self, _change: &Change) -> Result<(), DataBaseError> {
+        Ok(())
+    }
+    /// True if `apply` already wrote the change, so nothing is ever unsaved
+    fn saves_changes(&self) -> bool {
+        false
+    }
+    /// Number of lates and their minutes per student, counted with the
+    /// backend's own indexes. `None` if it has none, the caller counts then.
+    fn lates_per_person(
+        &self,
+        _range: Option<(NaiveDate, NaiveDate)>,
+    ) -> Option<Result<Vec<Lates>, DataBaseError>> {
+        None
+    }
+    /// All entries of a date range, both ends included, `None` like above
+    fn entries_between(
+        &self,
+        _from: NaiveDate,
+        _to: NaiveDate,
+    ) -> Option<Result<Vec<Entry>, DataBaseError>> {
+        None
+    }
+    fn path(&self) -> &str;
+}
+
+/// The whole database as one pretty printed JSON file, rewritten on every save
+pub struct JsonStorage {
+    path: String,
+}
+
+impl JsonStorage {
+    pub fn new(path: &str) -> Self {
+        JsonStorage {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&self) -> Result<(DataBase, bool), DataBaseError> {
+        DataBase::read_or_recover(&self.path)
+    }
+    fn save(&self, db: &DataBase) -> Result<(), DataBaseError> {
+        db.write_file(&self.path)
+    }
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+pub fn open(kind: StorageKind, path: &str) -> Result<Arc<dyn Storage>, DataBaseError> {
+    match kind {
+        StorageKind::Json => Ok(Arc::new(JsonStorage::new(path))),
+        #[cfg(not(target_arch = "wasm32"))]
+        StorageKind::Sqlite => Ok(Arc::new(crate::sqlite::SqliteStorage::open(path)?)),
+        #[cfg(target_arch = "wasm32")]
+        StorageKind::Sqlite => Err(DataBaseError::Other(
+            "SQLite ist in dieser Version nicht verfügbar".to_string(),
+        )),
+    }
+}
+
+/// Loads a database to look at without writing anything next to it, unlike
+/// `open` followed by `load`. A missing or broken file is an error.
+pub fn read_only(path: &str) -> Result<DataBase, DataBaseError> {
+    match StorageKind::from_path(path) {
+        StorageKind::Json => DataBase::read_only(path),
+        #[cfg(not(target_arch = "wasm32"))]
+        StorageKind::Sqlite => crate::sqlite::SqliteStorage::read_only(path),
+        #[cfg(target_arch = "wasm32")]
+        StorageKind::Sqlite => Err(DataBaseError::Other(
+            "SQLite ist in dieser Version nicht verfügbar".to_string(),
+        )),
+    }
+}
+
+/// Copies a database from one file into another, the formats follow the
+/// file extensions
+pub fn convert(from: &str, to: &str) -> Result<DataBase, DataBaseError> {
+    let (db, _) = open(StorageKind::from_path(from), from)?.load()?;
+    open(StorageKind::from_path(to), to)?.save(&db)?;
+    Ok(db)
+}
@@ -0,0 +1,191 @@
+use crate::bootstrap::*;
+use crate::db::{Change, Subject, SubjectId, SUBJECT_COLORS};
+use crate::themes::{self, styled_button, ColorType};
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use iced::widget::{button, column, horizontal_space, row, text, text_input};
+use iced::{Alignment, Element, Task};
+
+#[derive(Debug, Clone, Default)]
+pub struct SubjectsState {
+    name: String,
+    code: String,
+    color: String,
+    editing: Option<SubjectId>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SubjectsMsg {
+    Name(String),
+    Code(String),
+    Color(String),
+    Save,
+    Edit(SubjectId),
+    Cancel,
+    ToggleActive(SubjectId),
+}
+
+pub fn update_subjects(app: &mut App, msg: SubjectsMsg) -> Task<Message> {
+    match msg {
+        SubjectsMsg::Name(s) => app.subjects.name = s,
+        SubjectsMsg::Code(s) => app.subjects.code = s,
+        SubjectsMsg::Color(s) => app.subjects.color = s,
+        SubjectsMsg::Save => {
+            let Some(cohort) = app.cohort else {
+                app.notify(Toast::new(
+                    "Fehler",
+                    "Zuerst eine Klasse anlegen",
+                    Status::Danger,
+                ));
+                return Task::none();
+            };
+            let name = app.subjects.name.trim().to_string();
+            if name.is_empty() {
+                app.notify(Toast::new("Fehler", "Name fehlt", Status::Danger));
+                return Task::none();
+            }
+            let id = app.subjects.editing.unwrap_or(app.db.next_subject_id());
+            let color = match app.subjects.color.trim() {
+                "" => SUBJECT_COLORS[id as usize % SUBJECT_COLORS.len()],
+                c => match Subject::parse_hex(c) {
+                    Some(c) => c,
+                    None => {
+                        app.notify(Toast::new(
+                            "Fehler",
+                            "Farbe im Format #rrggbb angeben",
+                            Status::Danger,
+                        ));
+                        return Task::none();
+                    }
+                },
+            };
+            let code = match app.subjects.code.trim() {
+                "" => name.clone(),
+                c => c.to_string(),
+            };
+            let mut subjects = app.db.subjects.clone();
+            match subjects.iter_mut().find(|s| s.id == id) {
+                Some(s) => {
+                    s.name = name;
+                    s.code = code;
+                    s.color = color;
+                }
+                None => subjects.push(Subject {
+                    id,
+                    cohort,
+                    name,
+                    code,
+                    color,
+                    active: true,
+                    slots: vec![],
+                }),
+            }
+            app.commit(Change::SetSubjects(subjects));
+            app.subjects = SubjectsState::default();
+            app.refresh_pickers();
+        }
+        SubjectsMsg::Edit(id) => {
+            if let Some(s) = app.db.subject(id) {
+                app.subjects = SubjectsState {
+                    name: s.name.clone(),
+                    code: s.code.clone(),
+                    color: s.hex(),
+                    editing: Some(id),
+                };
+            }
+        }
+        SubjectsMsg::Cancel => app.subjects = SubjectsState::default(),
+        SubjectsMsg::ToggleActive(id) => {
+            let mut subjects = app.db.subjects.clone();
+            if let Some(s) = subjects.iter_mut().find(|s| s.id == id) {
+                s.active = !s.active;
+            }
+            app.commit(Change::SetSubjects(subjects));
+            app.refresh_pickers();
+        }
+    }
+    Task::none()
+}
+
+pub fn subjects_view(app: &App) -> Element<Message> {
+    let mut subjects: Vec<Subject> = app
+        .db
+        .subjects
+        .iter()
+        .filter(|s| Some(s.cohort) == app.cohort)
+        .cloned()
+        .collect();
+    subjects.sort_by(|a, b| b.active.cmp(&a.active).then(a.name.cmp(&b.name)));
+
+    let mut list = column![].spacing(2);
+    for s in subjects {
+        let name = text(s.name.clone()).size(18);
+        list = list.push(
+            row![
+                text(icon_to_string(Bootstrap::CircleFill))
+                    .font(ICON_FONT)
+                    .color(s.iced_color()),
+                if s.active {
+                    name.style(themes::text_fg_succes)
+                } else {
+                    name.style(themes::text_fg_sec)
+                },
+                text(s.code.clone()).style(themes::text_fg_sec),
+                horizontal_space(),
+                button(
+                    text(icon_to_string(Bootstrap::PencilFill))
+                        .font(ICON_FONT)
+                        .style(themes::text_fg)
+                )
+                .on_press(Message::Subjects(SubjectsMsg::Edit(s.id)))
+                .style(button::text),
+                button(if s.active { "Archivieren" } else { "Reaktivieren" })
+                    .on_press(Message::Subjects(SubjectsMsg::ToggleActive(s.id)))
+                    .style(button::secondary),
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center),
+        );
+    }
+
+    let mut form = row![
+        text_input("Fach", &app.subjects.name)
+            .on_input(|s| Message::Subjects(SubjectsMsg::Name(s))),
+        text_input("Kürzel", &app.subjects.code)
+            .on_input(|s| Message::Subjects(SubjectsMsg::Code(s))),
+        text_input("Farbe (#rrggbb)", &app.subjects.color)
+            .on_input(|s| Message::Subjects(SubjectsMsg::Color(s)))
+            .on_submit(Message::Subjects(SubjectsMsg::Save)),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+    form = match app.subjects.editing {
+        Some(_) => form
+            .push(styled_button(
+                Bootstrap::CheckSquareFill,
+                "Speichern",
+                Message::Subjects(SubjectsMsg::Save),
+                ColorType::Succes,
+            ))
+            .push(styled_button(
+                Bootstrap::XSquareFill,
+                "Abbrechen",
+                Message::Subjects(SubjectsMsg::Cancel),
+                ColorType::Secondary,
+            )),
+        None => form.push(styled_button(
+            Bootstrap::PlusSquareFill,
+            "Hinzufügen",
+            Message::Subjects(SubjectsMsg::Save),
+            ColorType::Succes,
+        )),
+    };
+
+    column![
+        text("Fächer").size(22).style(themes::text_fg),
+        form,
+        list,
+    ]
+    .spacing(5)
+    .into()
+}
@@ -1,66 +1,117 @@
+use crate::calendar::Calendar;
+use crate::schedule::Schedules;
 use chrono::prelude::*;
 
-pub fn get_last_lesson() -> NaiveTime {
-    let now = Local::now().naive_local().time();
-    let last_lesson = lesson_starts().into_iter().filter(|&time| time < now).max();
+/// A lesson on a given day, `index` points into the bell schedule that
+/// applied on `date`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LessonSlot {
+    pub date: NaiveDate,
+    pub index: usize,
+}
 
-    match last_lesson {
-        Some(time) => time,
-        None => lesson_starts()[lesson_starts().len() - 1],
+impl LessonSlot {
+    pub fn start(&self, schedules: &Schedules) -> NaiveTime {
+        let periods = &schedules.for_date(self.date).periods;
+        periods
+            .get(self.index)
+            .or(periods.last())
+            .map(|p| p.start)
+            .unwrap_or_default()
     }
 }
-pub fn get_last_lesson_t(t: NaiveTime) -> NaiveTime {
-    let now = t;
-    let last_lesson = lesson_starts()
-        .into_iter()
-        .filter(|&time| time <= now)
-        .max();
 
-    match last_lesson {
-        Some(time) => time,
-        None => lesson_starts()[lesson_starts().len() - 1],
-    }
+/// Slots held on `date`, none if the schedule has no lessons that weekday
+fn held(schedules: &Schedules, date: NaiveDate) -> Vec<usize> {
+    let schedule = schedules.for_date(date);
+    (0..schedule.periods.len())
+        .filter(|i| schedule.is_held(*i, date.weekday()))
+        .collect()
 }
 
-pub fn get_prev_lesson(t: NaiveTime) -> NaiveTime {
-    let lessons = lesson_starts();
-    let last_lesson = get_last_lesson_t(t);
+/// The slot of `date` that started last at `time`, `None` before the first
+fn started_by(schedules: &Schedules, date: NaiveDate, time: NaiveTime) -> Option<LessonSlot> {
+    let schedule = schedules.for_date(date);
+    held(schedules, date)
+        .into_iter()
+        .rfind(|i| schedule.periods[*i].start <= time)
+        .map(|index| LessonSlot { date, index })
+}
+
+/// The slot for a date and time picked by hand: the lesson started last at
+/// `time`, or the first of the day if `time` is before every lesson
+pub fn snap(schedules: &Schedules, date: NaiveDate, time: NaiveTime) -> LessonSlot {
+    started_by(schedules, date, time).unwrap_or(LessonSlot {
+        date,
+        index: held(schedules, date).first().copied().unwrap_or(0),
+    })
+}
 
-    // Find the index of the last lesson
-    let mut index = lessons.len(); // Set to len() as a fallback
-    for (i, lesson) in lessons.iter().enumerate() {
-        if *lesson == last_lesson {
-            index = i;
-            break;
+/// The last lesson of the last school day before `date` that has lessons
+fn last_before(schedules: &Schedules, calendar: &Calendar, date: NaiveDate) -> Option<LessonSlot> {
+    let mut day = date;
+    // A schedule without any lessons still has to end somewhere
+    for _ in 0..366 {
+        day = calendar.prev_school_day(day);
+        if let Some(&index) = held(schedules, day).last() {
+            return Some(LessonSlot { date: day, index });
         }
     }
+    None
+}
 
-    // Return the previous lesson, cycling back to the last one if necessary
-    if index == 0 {
-        lessons[lessons.len() - 1]
-    } else {
-        lessons[index - 1]
+/// The first lesson of the first school day after `date` that has lessons
+fn first_after(schedules: &Schedules, calendar: &Calendar, date: NaiveDate) -> Option<LessonSlot> {
+    let mut day = date;
+    for _ in 0..366 {
+        day = calendar.next_school_day(day);
+        if let Some(&index) = held(schedules, day).first() {
+            return Some(LessonSlot { date: day, index });
+        }
     }
+    None
 }
 
-pub fn get_next_lesson(t: NaiveTime) -> NaiveTime {
-    let lessons = lesson_starts();
-    let last_lesson = get_last_lesson_t(t);
+/// The lesson that started last at `at`. Before the first lesson of a day,
+/// and on days without school, that is the last lesson of the school day
+/// before.
+pub fn last_lesson_at(schedules: &Schedules, calendar: &Calendar, at: NaiveDateTime) -> LessonSlot {
+    let date = at.date();
+    let today = match calendar.is_school_day(date) {
+        true => started_by(schedules, date, at.time()),
+        false => None,
+    };
+    today
+        .or_else(|| last_before(schedules, calendar, date))
+        .unwrap_or_else(|| snap(schedules, date, at.time()))
+}
 
-    // Find the index of the last lesson
-    let mut index = lessons.len(); // Set to len() as a fallback
-    for (i, lesson) in lessons.iter().enumerate() {
-        if *lesson == last_lesson {
-            index = i;
-            break;
-        }
+/// The lesson to record a late or an absence for right now
+pub fn current_lesson(schedules: &Schedules, calendar: &Calendar) -> LessonSlot {
+    last_lesson_at(schedules, calendar, Local::now().naive_local())
+}
+
+/// The lesson before `slot`, the last one of the previous school day if
+/// `slot` is the first of its day
+pub fn prev_lesson(schedules: &Schedules, calendar: &Calendar, slot: LessonSlot) -> LessonSlot {
+    match held(schedules, slot.date)
+        .into_iter()
+        .rfind(|i| *i < slot.index)
+    {
+        Some(index) => LessonSlot { index, ..slot },
+        None => last_before(schedules, calendar, slot.date).unwrap_or(slot),
     }
+}
 
-    // Return the next lesson, cycling back to the first one if necessary
-    if index == lessons.len() - 1 {
-        lessons[0]
-    } else {
-        lessons[index + 1]
+/// The lesson after `slot`, the first one of the next school day if `slot`
+/// is the last of its day
+pub fn next_lesson(schedules: &Schedules, calendar: &Calendar, slot: LessonSlot) -> LessonSlot {
+    match held(schedules, slot.date)
+        .into_iter()
+        .find(|i| *i > slot.index)
+    {
+        Some(index) => LessonSlot { index, ..slot },
+        None => first_after(schedules, calendar, slot.date).unwrap_or(slot),
     }
 }
 
@@ -68,21 +119,250 @@ pub fn get_today() -> NaiveDate {
     Local::now().date_naive()
 }
 
-fn lesson_starts() -> Vec<NaiveTime> {
-    let times = [
-        (7, 45),
-        (8, 40),
-        (9, 35),
-        (10, 35),
-        (11, 30),
-        (12, 25),
-        (13, 20),
-        (14, 15),
-        (15, 10),
-        (16, 05),
-    ];
-    times
-        .into_iter()
-        .map(|x| NaiveTime::from_hms_opt(x.0, x.1, 0).expect("Couldnt create time"))
-        .collect()
+/// The day `n` school days after `date`, weekends and days off don't count
+pub fn add_school_days(date: NaiveDate, n: u32, calendar: &Calendar) -> NaiveDate {
+    let mut day = date;
+    for _ in 0..n {
+        day = calendar.next_school_day(day);
+    }
+    day
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::DayOff;
+    use crate::schedule::BellSchedule;
+
+    fn day(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn at(date: NaiveDate, h: u32, m: u32) -> NaiveDateTime {
+        date.and_hms_opt(h, m, 0).unwrap()
+    }
+
+    fn slot(date: NaiveDate, index: usize) -> LessonSlot {
+        LessonSlot { date, index }
+    }
+
+    fn monday() -> NaiveDate {
+        day(2024, 3, 4)
+    }
+
+    fn holidays(name: &str, from: NaiveDate, to: NaiveDate) -> Calendar {
+        Calendar {
+            terms: vec![],
+            days_off: vec![DayOff {
+                name: name.to_string(),
+                from,
+                to,
+            }],
+        }
+    }
+
+    /// The default times, but Wednesday ends after the sixth lesson and
+    /// Thursday starts with the second
+    fn short_days() -> Schedules {
+        let mut schedule = BellSchedule::default();
+        for (i, p) in schedule.periods.iter_mut().enumerate() {
+            if i >= 6 {
+                p.days.retain(|d| *d != Weekday::Wed);
+            }
+            if i == 0 {
+                p.days.retain(|d| *d != Weekday::Thu);
+            }
+        }
+        Schedules(vec![schedule])
+    }
+
+    #[test]
+    fn before_first_lesson_is_last_lesson_of_day_before() {
+        let schedules = Schedules::default();
+        let cal = Calendar::default();
+        let tuesday = day(2024, 3, 5);
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(tuesday, 7, 44)),
+            slot(monday(), 9)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(tuesday, 0, 0)),
+            slot(monday(), 9)
+        );
+    }
+
+    #[test]
+    fn monday_morning_is_friday_afternoon() {
+        let schedules = Schedules::default();
+        let cal = Calendar::default();
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(monday(), 7, 0)),
+            slot(day(2024, 3, 1), 9)
+        );
+    }
+
+    #[test]
+    fn lesson_starts_at_its_first_minute() {
+        let schedules = Schedules::default();
+        let cal = Calendar::default();
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(monday(), 7, 45)),
+            slot(monday(), 0)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(monday(), 8, 39)),
+            slot(monday(), 0)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(monday(), 8, 40)),
+            slot(monday(), 1)
+        );
+    }
+
+    #[test]
+    fn evening_is_last_lesson_of_the_day() {
+        let schedules = Schedules::default();
+        let cal = Calendar::default();
+        let last = last_lesson_at(&schedules, &cal, at(monday(), 23, 59));
+        assert_eq!(last, slot(monday(), 9));
+        assert_eq!(
+            last.start(&schedules),
+            NaiveTime::from_hms_opt(16, 5, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn weekend_is_friday() {
+        let schedules = Schedules::default();
+        let cal = Calendar::default();
+        let friday = day(2024, 3, 8);
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(day(2024, 3, 9), 10, 0)),
+            slot(friday, 9)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(day(2024, 3, 10), 23, 0)),
+            slot(friday, 9)
+        );
+    }
+
+    #[test]
+    fn stepping_rolls_over_to_neighbouring_school_days() {
+        let schedules = Schedules::default();
+        let cal = Calendar::default();
+        let friday = day(2024, 3, 8);
+        let next_monday = day(2024, 3, 11);
+        assert_eq!(
+            next_lesson(&schedules, &cal, slot(friday, 9)),
+            slot(next_monday, 0)
+        );
+        assert_eq!(
+            prev_lesson(&schedules, &cal, slot(next_monday, 0)),
+            slot(friday, 9)
+        );
+        assert_eq!(
+            next_lesson(&schedules, &cal, slot(monday(), 3)),
+            slot(monday(), 4)
+        );
+        assert_eq!(
+            prev_lesson(&schedules, &cal, slot(monday(), 3)),
+            slot(monday(), 2)
+        );
+    }
+
+    #[test]
+    fn holidays_are_skipped() {
+        let schedules = Schedules::default();
+        let cal = holidays("Sportferien", day(2024, 3, 11), day(2024, 3, 15));
+        let friday = day(2024, 3, 8);
+        let back = day(2024, 3, 18);
+        assert_eq!(
+            next_lesson(&schedules, &cal, slot(friday, 9)),
+            slot(back, 0)
+        );
+        assert_eq!(
+            prev_lesson(&schedules, &cal, slot(back, 0)),
+            slot(friday, 9)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(day(2024, 3, 13), 10, 0)),
+            slot(friday, 9)
+        );
+    }
+
+    #[test]
+    fn short_days_only_have_their_own_lessons() {
+        let schedules = short_days();
+        let cal = Calendar::default();
+        let wednesday = day(2024, 3, 6);
+        let thursday = day(2024, 3, 7);
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(wednesday, 15, 0)),
+            slot(wednesday, 5)
+        );
+        assert_eq!(
+            next_lesson(&schedules, &cal, slot(wednesday, 5)),
+            slot(thursday, 1)
+        );
+        assert_eq!(
+            prev_lesson(&schedules, &cal, slot(thursday, 1)),
+            slot(wednesday, 5)
+        );
+        // Thursday's first lesson starts at 08:40
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(thursday, 8, 0)),
+            slot(wednesday, 5)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(thursday, 8, 40)),
+            slot(thursday, 1)
+        );
+    }
+
+    #[test]
+    fn weekdays_without_lessons_are_skipped() {
+        let mut schedule = BellSchedule::default();
+        for p in schedule.periods.iter_mut() {
+            p.days.retain(|d| *d != Weekday::Wed);
+        }
+        let schedules = Schedules(vec![schedule]);
+        let cal = Calendar::default();
+        let tuesday = day(2024, 3, 5);
+        let wednesday = day(2024, 3, 6);
+        let thursday = day(2024, 3, 7);
+        assert_eq!(
+            next_lesson(&schedules, &cal, slot(tuesday, 9)),
+            slot(thursday, 0)
+        );
+        assert_eq!(
+            prev_lesson(&schedules, &cal, slot(thursday, 0)),
+            slot(tuesday, 9)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(wednesday, 10, 0)),
+            slot(tuesday, 9)
+        );
+        assert_eq!(
+            last_lesson_at(&schedules, &cal, at(thursday, 7, 0)),
+            slot(tuesday, 9)
+        );
+    }
+
+    #[test]
+    fn snapping_a_picked_time() {
+        let schedules = short_days();
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let wednesday = day(2024, 3, 6);
+        let thursday = day(2024, 3, 7);
+        assert_eq!(snap(&schedules, thursday, time(7, 45)), slot(thursday, 1));
+        assert_eq!(snap(&schedules, thursday, time(10, 0)), slot(thursday, 2));
+        assert_eq!(snap(&schedules, wednesday, time(16, 5)), slot(wednesday, 5));
+    }
+
+    #[test]
+    fn school_days_skip_weekends_and_days_off() {
+        let cal = holidays("Auffahrt", day(2024, 3, 7), day(2024, 3, 7));
+        assert_eq!(add_school_days(monday(), 4, &cal), day(2024, 3, 11));
+        assert_eq!(add_school_days(monday(), 0, &cal), monday());
+    }
 }
@@ -0,0 +1,124 @@
+use crate::db::{Change, CohortId, DataBase, Subject, SubjectId, WeekSlot};
+use crate::schedule::{weekday_name, WEEKDAYS};
+use crate::spans::Slot;
+use crate::themes;
+use crate::time::LessonSlot;
+use crate::toast::{Status, Toast};
+use crate::{App, Message};
+use chrono::Datelike;
+use iced::widget::{column, pick_list, row, text};
+use iced::{Alignment, Element, Task};
+
+/// What the timetable says about one lesson
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Planned {
+    /// `None` for a free slot
+    pub subject: Option<SubjectId>,
+    pub first_lesson: bool,
+}
+
+/// Looks up the lesson in `slot`. `None` if the class has nothing planned
+/// that day, so the form keeps what the user picked.
+pub fn planned(db: &DataBase, cohort: Option<CohortId>, slot: LessonSlot) -> Option<Planned> {
+    let weekday = slot.date.weekday();
+    let first = db.first_planned_slot(cohort, weekday)?;
+    Some(Planned {
+        subject: db.planned_subject(cohort, weekday, slot.index),
+        first_lesson: slot.index == first,
+    })
+}
+
+/// Entry of a timetable cell picker
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell(pub Option<Subject>);
+
+impl std::fmt::Display for Cell {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.0 {
+            Some(s) => write!(f, "{}", s.code),
+            None => write!(f, "–"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TimetableMsg {
+    Set(WeekSlot, Cell),
+}
+
+pub fn update_timetable(app: &mut App, msg: TimetableMsg) -> Task<Message> {
+    match msg {
+        TimetableMsg::Set(at, cell) => {
+            let Some(cohort) = app.cohort else {
+                app.notify(Toast::new(
+                    "Fehler",
+                    "Zuerst eine Klasse anlegen",
+                    Status::Danger,
+                ));
+                return Task::none();
+            };
+            let mut subjects = app.db.subjects.clone();
+            for s in subjects.iter_mut().filter(|s| s.cohort == cohort) {
+                s.slots.retain(|w| *w != at);
+                if cell.0.as_ref().is_some_and(|c| c.id == s.id) {
+                    s.slots.push(at);
+                }
+            }
+            app.commit(Change::SetSubjects(subjects));
+        }
+    }
+    Task::none()
+}
+
+/// Weekly timetable section of the settings
+pub fn timetable_view(app: &App) -> Element<Message> {
+    let schedule = app.settings.schedules.today();
+    let mut choices = vec![Cell(None)];
+    choices.extend(
+        app.db
+            .active_subjects(app.cohort)
+            .into_iter()
+            .map(|s| Cell(Some(s))),
+    );
+
+    let mut header = row![text("").width(150)].spacing(5);
+    for day in WEEKDAYS {
+        header = header.push(text(weekday_name(day)).width(100));
+    }
+    let mut grid = column![header].spacing(2);
+    for slot in Slot::all(schedule) {
+        let mut line = row![text(slot.to_string()).width(150)]
+            .spacing(5)
+            .align_y(Alignment::Center);
+        for weekday in WEEKDAYS {
+            if !schedule.is_held(slot.index, weekday) {
+                line = line.push(text("").width(100));
+                continue;
+            }
+            let at = WeekSlot {
+                weekday,
+                slot: slot.index,
+            };
+            let current = app
+                .db
+                .planned_subject(app.cohort, weekday, slot.index)
+                .and_then(|id| app.db.subject(id).cloned());
+            line = line.push(
+                pick_list(choices.clone(), Some(Cell(current)), move |c| {
+                    Message::Timetable(TimetableMsg::Set(at, c))
+                })
+                .width(100),
+            );
+        }
+        grid = grid.push(line);
+    }
+
+    column![
+        text("Stundenplan").size(22).style(themes::text_fg),
+        text("Neue Verspätungen und Absenzen übernehmen Fach und erste Lektion von hier.")
+            .style(themes::text_fg_sec),
+        grid,
+    ]
+    .spacing(5)
+    .into()
+}
@@ -47,18 +47,37 @@ pub struct Toast {
     pub title: String,
     pub body: String,
     pub status: Status,
+    pub action: Option<Action>,
     time: chrono::NaiveTime,
 }
 
+/// Button inside a toast, `id` is handed back when it is pressed
+#[derive(Debug, Clone, Default)]
+pub struct Action {
+    pub label: String,
+    pub id: u64,
+}
+
 impl Toast {
     pub fn new(title: &str, body: &str, status: Status) -> Self {
         Toast {
             title: title.to_string(),
             body: body.to_string(),
             status,
+            action: None,
             time: chrono::Local::now().naive_local().time(),
         }
     }
+
+    pub fn with_action(self, label: &str, id: u64) -> Self {
+        Toast {
+            action: Some(Action {
+                label: label.to_string(),
+                id,
+            }),
+            ..self
+        }
+    }
 }
 
 pub struct Manager<'a, Message> {
@@ -76,11 +95,32 @@ where
         content: impl Into<Element<'a, Message>>,
         toasts: &'a [Toast],
         on_close: impl Fn(usize) -> Message + 'a,
+        on_action: impl Fn(usize, u64) -> Message + 'a,
     ) -> Self {
         let toasts = toasts
             .iter()
             .enumerate()
             .map(|(index, toast)| {
+                let mut body = column![
+                    text(toast.title.as_str())
+                        .size(18)
+                        .style(match toast.status {
+                            Status::Primary => text::primary,
+                            Status::Secondary => text::secondary,
+                            Status::Success => text::success,
+                            Status::Danger => text::danger,
+                        }),
+                    text(toast.body.as_str()).size(11),
+                ]
+                .spacing(4);
+                if let Some(action) = &toast.action {
+                    body = body.push(
+                        button(text(action.label.as_str()).size(12))
+                            .on_press(on_action(index, action.id))
+                            .padding([2, 8])
+                            .style(button::secondary),
+                    );
+                }
                 container(column![
                     container(row![horizontal_space().height(1)].align_y(Center))
                         .width(Fill)
@@ -91,20 +131,10 @@ where
                             Status::Success => success,
                             Status::Danger => danger,
                         }),
-                    container(column![
-                        text(toast.title.as_str())
-                            .size(18)
-                            .style(match toast.status {
-                                Status::Primary => text::primary,
-                                Status::Secondary => text::secondary,
-                                Status::Success => text::success,
-                                Status::Danger => text::danger,
-                            }),
-                        text(toast.body.as_str()).size(11)
-                    ])
-                    .width(Fill)
-                    .padding(5)
-                    .style(container::rounded_box),
+                    container(body)
+                        .width(Fill)
+                        .padding(5)
+                        .style(container::rounded_box),
                 ])
                 .max_width(200)
                 .into()
@@ -0,0 +1,198 @@
+use crate::bootstrap::*;
+use crate::crypt;
+use crate::db::DataBaseError;
+use crate::storage::StorageKind;
+use crate::themes::{self, styled_button, ColorType};
+use crate::toast::{Status, Toast};
+use crate::{backup, App, Message};
+use iced::widget::{column, container, row, text, text_input};
+use iced::{Alignment, Element, Length, Task};
+use zeroize::{Zeroize, Zeroizing};
+
+#[derive(Debug, Clone, Default)]
+pub struct UnlockState {
+    passphrase: Zeroizing<String>,
+    error: Option<String>,
+    new_passphrase: Zeroizing<String>,
+    repeat: Zeroizing<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum UnlockMsg {
+    /// The database turned out to be encrypted, with the reason if a
+    /// passphrase was already tried
+    Required(Option<String>),
+    Passphrase(String),
+    Unlock,
+    NewPassphrase(String),
+    Repeat(String),
+    SetPassphrase,
+    RemovePassphrase,
+}
+
+/// Shortest passphrase accepted for new passphrases
+const MIN_LEN: usize = 8;
+
+pub fn update_unlock(app: &mut App, msg: UnlockMsg) -> Task<Message> {
+    match msg {
+        UnlockMsg::Required(error) => {
+            // Nothing may be written until the right passphrase is known
+            crypt::lock();
+            app.db_locked = true;
+            app.unlock.error = error;
+            app.view = crate::ViewControl::UNLOCK;
+        }
+        UnlockMsg::Passphrase(s) => app.unlock.passphrase = Zeroizing::new(s),
+        UnlockMsg::Unlock => {
+            crypt::unlock(&app.unlock.passphrase);
+            app.unlock.passphrase.zeroize();
+            app.unlock.error = None;
+            let storage = app.storage.clone();
+            return Task::perform(async move { storage.load() }, crate::db_loaded);
+        }
+        UnlockMsg::NewPassphrase(s) => app.unlock.new_passphrase = Zeroizing::new(s),
+        UnlockMsg::Repeat(s) => app.unlock.repeat = Zeroizing::new(s),
+        UnlockMsg::SetPassphrase => {
+            if app.unlock.new_passphrase.chars().count() < MIN_LEN {
+                app.notify(Toast::new(
+                    "Nicht geändert",
+                    "Das Passwort braucht mindestens 8 Zeichen",
+                    Status::Danger,
+                ));
+                return Task::none();
+            }
+            if app.unlock.new_passphrase != app.unlock.repeat {
+                app.notify(Toast::new(
+                    "Nicht geändert",
+                    "Die Passwörter stimmen nicht überein",
+                    Status::Danger,
+                ));
+                return Task::none();
+            }
+            let passphrase = std::mem::take(&mut app.unlock.new_passphrase);
+            app.unlock.repeat.zeroize();
+            return switch(app, Some(&passphrase), "Die Daten sind jetzt verschlüsselt");
+        }
+        UnlockMsg::RemovePassphrase => {
+            app.unlock.new_passphrase.zeroize();
+            app.unlock.repeat.zeroize();
+            return switch(app, None, "Die Daten werden wieder unverschlüsselt gespeichert");
+        }
+    }
+    Task::none()
+}
+
+/// Writes the database and all backups again with the new passphrase. The
+/// database is written twice so the `.prev` copy of the old file is
+/// replaced as well, and the journal may still hold the old format.
+fn switch(app: &mut App, passphrase: Option<&str>, done: &str) -> Task<Message> {
+    if app.db_locked || app.settings.storage != StorageKind::Json {
+        app.notify(Toast::new(
+            "Nicht geändert",
+            "Verschlüsselung gibt es nur für die JSON-Datei",
+            Status::Danger,
+        ));
+        return Task::none();
+    }
+    let backups = backup::read_all_plain();
+    match passphrase {
+        Some(p) => crypt::set_passphrase(p),
+        None => crypt::lock(),
+    }
+    match rewrite(app, backups) {
+        Ok(_) => {
+            app.mark_saved();
+            app.notify(Toast::new("Verschlüsselung", done, Status::Success));
+            Task::done(Message::Backup(backup::BackupMsg::Refresh))
+        }
+        Err(e) => Task::done(Message::DBError(
+            "Verschlüsselung nicht vollständig geändert".to_string(),
+            e,
+        )),
+    }
+}
+
+fn rewrite(app: &App, backups: Vec<(String, String)>) -> Result<(), DataBaseError> {
+    app.storage.save(&app.db)?;
+    app.storage.save(&app.db)?;
+    app.journal.clear()?;
+    backup::rewrite_all(backups)
+}
+
+pub fn unlock_view(app: &App) -> Element<Message> {
+    let mut col = column![
+        text(icon_to_string(Bootstrap::LockFill))
+            .font(ICON_FONT)
+            .size(40)
+            .style(themes::text_fg),
+        text("Datenbank gesperrt").size(26).style(themes::text_fg),
+        text("Die Daten sind verschlüsselt. Bitte das Passwort eingeben.")
+            .style(themes::text_fg_sec),
+        row![
+            text_input("Passwort", &app.unlock.passphrase)
+                .secure(true)
+                .on_input(|s| Message::Unlock(UnlockMsg::Passphrase(s)))
+                .on_submit(Message::Unlock(UnlockMsg::Unlock))
+                .width(250),
+            styled_button(
+                Bootstrap::Unlock,
+                "Entsperren",
+                Message::Unlock(UnlockMsg::Unlock),
+                ColorType::Primary,
+            ),
+        ]
+        .spacing(5)
+        .align_y(Alignment::Center),
+    ]
+    .spacing(10)
+    .align_x(Alignment::Center);
+    if let Some(error) = &app.unlock.error {
+        col = col.push(text(error.clone()).style(themes::text_fg_danger));
+    }
+    container(col).center(Length::Fill).into()
+}
+
+/// Passphrase section of the settings
+pub fn passphrase_view(app: &App) -> Element<Message> {
+    let status = match (crypt::active(), app.settings.storage) {
+        (_, StorageKind::Sqlite) => "Nur mit der JSON-Datei verfügbar",
+        (true, _) => "Datenbank, Journal und Backups sind verschlüsselt",
+        (false, _) => "Die Daten werden unverschlüsselt gespeichert",
+    };
+    let mut actions = row![
+        text_input("Neues Passwort", &app.unlock.new_passphrase)
+            .secure(true)
+            .on_input(|s| Message::Unlock(UnlockMsg::NewPassphrase(s))),
+        text_input("Wiederholen", &app.unlock.repeat)
+            .secure(true)
+            .on_input(|s| Message::Unlock(UnlockMsg::Repeat(s)))
+            .on_submit(Message::Unlock(UnlockMsg::SetPassphrase)),
+        styled_button(
+            Bootstrap::LockFill,
+            if crypt::active() {
+                "Passwort ändern"
+            } else {
+                "Verschlüsseln"
+            },
+            Message::Unlock(UnlockMsg::SetPassphrase),
+            ColorType::Primary,
+        ),
+    ]
+    .spacing(5)
+    .align_y(Alignment::Center);
+    if crypt::active() {
+        actions = actions.push(styled_button(
+            Bootstrap::Unlock,
+            "Entschlüsseln",
+            Message::Unlock(UnlockMsg::RemovePassphrase),
+            ColorType::Danger,
+        ));
+    }
+    column![
+        text("Verschlüsselung").size(22).style(themes::text_fg),
+        text(status).style(themes::text_fg_sec),
+        actions,
+    ]
+    .spacing(5)
+    .into()
+}